@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE "timeslots" ADD COLUMN "hourly_rate" integer;
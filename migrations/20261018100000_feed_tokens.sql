@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE feed_tokens (
+	id uuid PRIMARY KEY,
+	user_id varchar(255) NOT NULL UNIQUE,
+	token_hash bytea NOT NULL UNIQUE,
+	created timestamp with time zone NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Add migration script here
+CREATE TABLE account_deletions (
+	user_id varchar(255) PRIMARY KEY,
+	requested timestamp with time zone NOT NULL,
+	scheduled timestamp with time zone NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE "timeslots" ADD COLUMN "anonymised" boolean NOT NULL DEFAULT false;
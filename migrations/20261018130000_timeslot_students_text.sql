@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Encrypted names are longer than the plaintext ones.
+ALTER TABLE "timeslots" ALTER COLUMN "students" TYPE text[];
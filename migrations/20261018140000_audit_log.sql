@@ -0,0 +1,31 @@
+-- Add migration script here
+CREATE TYPE "audit_action" AS ENUM (
+	'create',
+	'update',
+	'delete'
+);
+
+-- Doesn't reference timeslots, since the log has to outlive deleted rows.
+CREATE TABLE audit_log (
+	id bigserial PRIMARY KEY,
+	user_id varchar(255) NOT NULL,
+	request_id text,
+	timeslot_id uuid NOT NULL,
+	index integer,
+	action audit_action NOT NULL,
+	before jsonb,
+	after jsonb,
+	created timestamp with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX audit_log_object ON audit_log (user_id, timeslot_id, index);
+
+-- Rows are only ever deleted to comply with account deletion and data retention.
+CREATE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+	RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only BEFORE UPDATE ON audit_log
+	FOR EACH ROW EXECUTE FUNCTION audit_log_append_only();
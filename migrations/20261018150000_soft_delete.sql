@@ -0,0 +1,10 @@
+-- Add migration script here
+ALTER TABLE "timeslots" ADD COLUMN "deleted_at" timestamp with time zone;
+ALTER TABLE "entries" ADD COLUMN "deleted_at" timestamp with time zone;
+
+-- Deleted entries shouldn't prevent creating a new entry with the same index.
+DROP INDEX entries_index_timeslot_id_idx;
+
+CREATE UNIQUE INDEX entries_index_timeslot_id_idx ON entries (index, timeslot_id) WHERE deleted_at IS NULL;
+
+ALTER TYPE "audit_action" ADD VALUE 'restore';
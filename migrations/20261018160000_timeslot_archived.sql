@@ -0,0 +1,3 @@
+-- Add migration script here
+-- NULL means the timeslot hasn't been archived or unarchived by hand, so it can be archived automatically.
+ALTER TABLE "timeslots" ADD COLUMN "archived" boolean;
@@ -0,0 +1,339 @@
+use std::collections::hash_map::Entry as MapEntry;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+
+use axum::extract::{Json, Query, State};
+use axum::http::StatusCode;
+use axum::Extension;
+
+use chrono::{DateTime, Duration, Utc};
+
+use itertools::Itertools;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use tower_request_id::RequestId;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::api::logic::backup::{AccountBackup, BackupTimeslot, BACKUP_VERSION};
+use crate::api::logic::check_object_belong_to_userid;
+use crate::api::timeslot::{check_overlap, OverlapQuery};
+use crate::api::util::prelude::*;
+use crate::api::AppState;
+use crate::auth::UserId;
+use crate::db::model::{convert_entry, convert_ts, Entry};
+use crate::db::queries;
+use crate::db::queries::account::{get_deletion, schedule_deletion, AccountDeletion};
+use crate::db::queries::audit::{log_entry, log_timeslot, AuditAction, AuditContext};
+use crate::db::queries::entry::{get_entries_by_timeslot_id, insert_entry, InsertEntryError};
+use crate::db::queries::timeslot::{get_timeslots, insert_timeslot};
+
+pub async fn export(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+) -> WebResult<AccountBackup, &'static str> {
+	let user_timeslots = get_timeslots(&db, &u, true).await?;
+
+	check_object_belong_to_userid(user_timeslots.iter(), &u)?;
+
+	let mut timeslots = Vec::with_capacity(user_timeslots.len());
+
+	for ts in user_timeslots {
+		let entries = get_entries_by_timeslot_id(&db, &u, ts.id).await?;
+
+		check_object_belong_to_userid(entries.iter(), &u)?;
+
+		timeslots.push(BackupTimeslot::new(ts, entries));
+	}
+
+	Ok(AccountBackup {
+		version: BACKUP_VERSION,
+		exported: Utc::now(),
+		timeslots,
+	}
+	.into())
+}
+
+// Timeslots conflict, if an existing timeslot has the same subject, time, timerange and timezone.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStrategy {
+	// Don't import conflicting timeslots
+	#[default]
+	Skip,
+	// Import conflicting timeslots as new timeslots
+	Duplicate,
+	// Import the entries of conflicting timeslots into the existing timeslot
+	Merge,
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+	#[serde(default)]
+	on_conflict: ConflictStrategy,
+}
+
+#[derive(Serialize, Default)]
+pub struct ImportReturn {
+	timeslots_created: u32,
+	timeslots_skipped: u32,
+	timeslots_merged: u32,
+	entries_created: u32,
+	entries_skipped: u32,
+	// Ids in the backup mapped to the ids they have been imported as.
+	id_map: HashMap<Uuid, Uuid>,
+}
+
+pub enum AccountImportError {
+	UnsupportedVersion,
+	InvalidBackup(Vec<String>),
+	DuplicateEntry,
+}
+
+impl From<AccountImportError> for WebError<Value> {
+	fn from(v: AccountImportError) -> WebError<Value> {
+		use AccountImportError::*;
+		match v {
+			UnsupportedVersion => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				"unsupported backup version".into(),
+			)
+				.into(),
+			InvalidBackup(errors) => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				serde_json::json!({ "invalid_backup": errors }),
+			)
+				.into(),
+			DuplicateEntry => (StatusCode::CONFLICT, "duplicate entry".into()).into(),
+		}
+	}
+}
+
+pub async fn import(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+	Query(q): Query<ImportQuery>,
+	Query(overlap): Query<OverlapQuery>,
+	Json(backup): Json<AccountBackup>,
+) -> WebResult<ImportReturn, Value> {
+	if backup.version != BACKUP_VERSION {
+		return Err(AccountImportError::UnsupportedVersion)?;
+	}
+
+	let existing = get_timeslots(&db, &u, true).await?;
+
+	let errors: Vec<_> = backup
+		.timeslots
+		.iter()
+		.filter_map(|ts| match existing.iter().find(|e| ts.conflicts_with(e)) {
+			Some(c) if q.on_conflict == ConflictStrategy::Merge => {
+				ts.validate().and_then(|()| ts.validate_merge(c)).err()
+			}
+			_ => ts.validate().err(),
+		})
+		.collect();
+
+	if !errors.is_empty() {
+		return Err(AccountImportError::InvalidBackup(errors))?;
+	}
+
+	if !overlap.allow_overlap {
+		let new: Vec<_> = backup
+			.timeslots
+			.iter()
+			.filter(|ts| {
+				q.on_conflict == ConflictStrategy::Duplicate
+					|| !existing.iter().any(|e| ts.conflicts_with(e))
+			})
+			.map(|ts| ts.to_create().to_web(&u))
+			.try_collect()?;
+
+		check_overlap(&db, &u, &new).await?;
+	}
+
+	let mut res = ImportReturn::default();
+
+	let audit = AuditContext::new(&u, &request_id);
+
+	// Several backup timeslots can be merged into the same timeslot, so the taken indices are tracked per target.
+	let mut taken_indices: HashMap<Uuid, HashSet<u32>> = HashMap::new();
+
+	// Either the whole backup gets imported or nothing.
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	for ts in backup.timeslots {
+		let conflict = existing.iter().find(|e| ts.conflicts_with(e));
+
+		let id = match (conflict, q.on_conflict) {
+			(Some(_), ConflictStrategy::Skip) => {
+				res.timeslots_skipped += 1;
+				continue;
+			}
+			(Some(c), ConflictStrategy::Merge) => {
+				if let MapEntry::Vacant(v) = taken_indices.entry(c.id) {
+					let indices = get_entries_by_timeslot_id(&db, &u, c.id)
+						.await?
+						.into_iter()
+						.map(|e| e.index)
+						.collect();
+
+					v.insert(indices);
+				}
+
+				res.timeslots_merged += 1;
+				c.id
+			}
+			(None, _) | (Some(_), ConflictStrategy::Duplicate) => {
+				let id = Uuid::new_v4();
+
+				let timeslot = ts.to_create().into_timeslot(&u, id)?;
+				let snapshot = convert_ts(timeslot.clone()).context("invalid timeslot")?;
+
+				insert_timeslot(&mut *tx, timeslot).await?;
+				log_timeslot(&mut *tx, &audit, AuditAction::Create, None, Some(&snapshot)).await?;
+
+				res.timeslots_created += 1;
+				id
+			}
+		};
+
+		res.id_map.insert(ts.id, id);
+
+		let taken = taken_indices.entry(id).or_default();
+
+		for e in ts.entries {
+			// Entries which already exist are skipped, since a unique violation would abort the transaction.
+			if !taken.insert(e.index) {
+				res.entries_skipped += 1;
+				continue;
+			}
+
+			let entry = Entry {
+				user_id: u.as_str().to_owned(),
+				index: e.index.try_into()?,
+				timeslot_id: id,
+				state_enum: e.state,
+				students: e.students,
+			};
+
+			let snapshot = convert_entry(entry.clone()).context("invalid entry")?;
+
+			match insert_entry(&mut *tx, entry).await {
+				Ok(()) => {
+					log_entry(&mut *tx, &audit, AuditAction::Create, None, Some(&snapshot)).await?;
+					res.entries_created += 1;
+				}
+				// Taken indices are skipped above, so this only happens on concurrent requests.
+				Err(InsertEntryError::Duplicate) => {
+					return Err(AccountImportError::DuplicateEntry)?;
+				}
+				Err(InsertEntryError::Other(e)) => Err(e)?,
+			}
+		}
+	}
+
+	tx.commit().await.context("couldn't commit transaction")?;
+
+	info!(
+		timeslots_created = res.timeslots_created,
+		entries_created = res.entries_created,
+		"imported account backup"
+	);
+
+	Ok((StatusCode::CREATED, res).into())
+}
+
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+	#[serde(default)]
+	confirm: bool,
+}
+
+#[derive(Serialize)]
+pub struct DeletionReturn {
+	requested: DateTime<Utc>,
+	scheduled: DateTime<Utc>,
+}
+
+impl From<AccountDeletion> for DeletionReturn {
+	fn from(d: AccountDeletion) -> Self {
+		DeletionReturn {
+			requested: d.requested,
+			scheduled: d.scheduled,
+		}
+	}
+}
+
+pub enum AccountDeletionError {
+	ConfirmationRequired { timeslots: usize },
+	NotScheduled,
+}
+
+impl From<AccountDeletionError> for WebError<Value> {
+	fn from(v: AccountDeletionError) -> WebError<Value> {
+		use AccountDeletionError::*;
+		match v {
+			ConfirmationRequired { timeslots } => (
+				StatusCode::PRECONDITION_REQUIRED,
+				serde_json::json!({
+					"confirmation_required": "all timeslots, entries and sessions will be deleted",
+					"timeslots": timeslots,
+				}),
+			)
+				.into(),
+			NotScheduled => (
+				StatusCode::NOT_FOUND,
+				"no account deletion scheduled".into(),
+			)
+				.into(),
+		}
+	}
+}
+
+// The account is only deleted after the grace period, until then the deletion can be cancelled.
+pub async fn delete(
+	State(AppState { db, cfg, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Query(q): Query<DeleteQuery>,
+) -> WebResult<DeletionReturn, Value> {
+	if !q.confirm {
+		let timeslots = get_timeslots(&db, &u, true).await?.len();
+
+		return Err(AccountDeletionError::ConfirmationRequired { timeslots })?;
+	}
+
+	let scheduled = Utc::now() + Duration::days(cfg.deletion.gracedays.into());
+
+	let deletion = schedule_deletion(&db, &u, scheduled).await?;
+
+	info!(scheduled=%deletion.scheduled, "account deletion scheduled");
+
+	Ok((StatusCode::ACCEPTED, DeletionReturn::from(deletion)).into())
+}
+
+pub async fn deletion_status(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+) -> WebResult<DeletionReturn, Value> {
+	match get_deletion(&db, &u).await? {
+		Some(d) => Ok(DeletionReturn::from(d).into()),
+		None => Err(AccountDeletionError::NotScheduled)?,
+	}
+}
+
+pub async fn cancel_deletion(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+) -> WebResult<&'static str, Value> {
+	if queries::account::cancel_deletion(&db, &u).await? == 0 {
+		return Err(AccountDeletionError::NotScheduled)?;
+	}
+
+	info!("account deletion cancelled");
+
+	Ok("cancelled".into())
+}
@@ -0,0 +1,106 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Extension;
+
+use chrono::{DateTime, Days, FixedOffset, Utc};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::logic::check_object_belong_to_userid;
+use crate::api::logic::entry::get_time_from_index_and_timeslot;
+use crate::api::logic::timeslot::get_index_range_timeslot;
+use crate::api::util::prelude::*;
+use crate::api::AppState;
+use crate::auth::UserId;
+use crate::db::queries::timeslot::get_timeslots;
+
+const DEFAULT_DAYS: u32 = 14;
+const MAX_DAYS: u32 = 366;
+
+#[derive(Deserialize)]
+pub struct AgendaQuery {
+	days: Option<u32>,
+}
+
+pub enum AgendaError {
+	InvalidDays,
+}
+
+impl From<AgendaError> for WebError<&'static str> {
+	fn from(v: AgendaError) -> WebError<&'static str> {
+		use AgendaError::*;
+		match v {
+			InvalidDays => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				"days must be between 1 and 366",
+			)
+				.into(),
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct AgendaItem {
+	timeslot_id: Uuid,
+	subject: String,
+	index: u32,
+	timestamp: DateTime<FixedOffset>,
+}
+
+// Upcoming occurrences of all timeslots, so clients don't have to query each timeslot.
+pub async fn query(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Query(q): Query<AgendaQuery>,
+) -> WebResult<Vec<AgendaItem>, &'static str> {
+	let days = q.days.unwrap_or(DEFAULT_DAYS);
+
+	if days == 0 || days > MAX_DAYS {
+		return Err(AgendaError::InvalidDays)?;
+	}
+
+	let now = Utc::now();
+
+	// Archived timeslots aren't taught anymore.
+	let timeslots = get_timeslots(&db, &u, false).await?;
+
+	check_object_belong_to_userid(timeslots.iter(), &u)?;
+
+	let mut res = Vec::new();
+
+	for ts in &timeslots {
+		// Dates are counted in the timezone of the timeslot, `until` is inclusive.
+		let today = now.with_timezone(&ts.timezone).date_naive();
+
+		let Some(until) = today.checked_add_days(Days::new((days - 1).into())) else {
+			continue;
+		};
+
+		let Some(indices) = get_index_range_timeslot(ts, today..until) else {
+			continue;
+		};
+
+		for index in indices.start..=indices.end {
+			let Some(timestamp) = get_time_from_index_and_timeslot(ts, index) else {
+				continue;
+			};
+
+			// The occurrence today might already be over.
+			if timestamp < now {
+				continue;
+			}
+
+			res.push(AgendaItem {
+				timeslot_id: ts.id,
+				subject: ts.subject.clone(),
+				index,
+				timestamp: timestamp.fixed_offset(),
+			});
+		}
+	}
+
+	res.sort_by_key(|a| a.timestamp);
+
+	Ok(res.into())
+}
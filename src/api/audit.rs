@@ -0,0 +1,27 @@
+use axum::extract::{Query, State};
+use axum::Extension;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api::util::prelude::*;
+use crate::api::AppState;
+use crate::auth::UserId;
+use crate::db::queries::audit::{get_audit_log, AuditLogEntry};
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+	timeslot_id: Uuid,
+	// Only returns the records of this entry
+	index: Option<u32>,
+}
+
+pub async fn query(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Query(q): Query<AuditQuery>,
+) -> WebResult<Vec<AuditLogEntry>, &'static str> {
+	let index = q.index.map(i32::try_from).transpose()?;
+
+	Ok(get_audit_log(&db, &u, q.timeslot_id, index).await?.into())
+}
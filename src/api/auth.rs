@@ -6,11 +6,12 @@ use axum::Extension;
 use axum_extra::extract::cookie::CookieJar;
 use serde::{Deserialize, Serialize};
 
+use sqlx::PgPool;
 use tracing::error;
 use url::Url;
 
 use crate::api::util::{WebError, WebResult};
-use crate::auth::{AuthenticatorError, UserId};
+use crate::auth::{verify_feed_token, Authenticator, AuthenticatorError, UserId};
 
 use super::util::TransposeResult;
 use super::AppState;
@@ -68,6 +69,7 @@ pub enum AuthError {
 	SessionInvalid,
 	SessionMissing,
 	SessionIdInvalid,
+	FeedTokenInvalid,
 }
 
 impl From<AuthError> for WebError<&'static str> {
@@ -81,6 +83,7 @@ impl From<AuthError> for WebError<&'static str> {
 				(StatusCode::UNAUTHORIZED, "session id cookie missing").into()
 			}
 			AuthError::SessionIdInvalid => (StatusCode::BAD_REQUEST, "invalid sessionid").into(),
+			AuthError::FeedTokenInvalid => (StatusCode::UNAUTHORIZED, "invalid feed token").into(),
 		}
 	}
 }
@@ -91,26 +94,45 @@ pub async fn auth_middleware(
 	mut req: Request,
 	next: Next,
 ) -> Response {
-	let session_id = match extract_session_id(&cookies) {
-		Ok(s) => s,
-		Err(e) => return WebError::<&'static str>::from(e).into_response(),
+	let user_id = match session_user_id(&db, &auth, &cookies).await {
+		Ok(u) => u,
+		Err(e) => return e.into_response(),
 	};
 
-	let user_id = match auth.verify(&db, session_id).await {
-		Ok(o) => match o {
-			Ok(u) => u,
-			Err(AuthenticatorError::NotAuthorized) => {
-				return WebError::<&'static str>::from(AuthError::SessionIncompleteOrExpired)
-					.into_response()
+	req.extensions_mut().insert(user_id);
+
+	next.run(req).await
+}
+
+#[derive(Deserialize)]
+pub struct FeedTokenQuery {
+	token: Option<String>,
+}
+
+// Only used for read-only routes, which calendar apps need to access without a session.
+pub async fn feed_auth_middleware(
+	State(AppState { db, auth, .. }): State<AppState>,
+	Query(q): Query<FeedTokenQuery>,
+	cookies: CookieJar,
+	mut req: Request,
+	next: Next,
+) -> Response {
+	let user_id = match q.token {
+		Some(token) => match verify_feed_token(&db, &token).await {
+			Ok(Some(u)) => u,
+			Ok(None) => {
+				tracing::trace!("couldn't find feed token");
+				return WebError::<&'static str>::from(AuthError::FeedTokenInvalid).into_response();
 			}
-			Err(AuthenticatorError::InvalidSession) => {
-				return WebError::<&'static str>::from(AuthError::SessionInvalid).into_response()
+			Err(e) => {
+				error!(?e, "server error while processing feed token");
+				return WebError::<&'static str>::internal_server_error().into_response();
 			}
 		},
-		Err(e) => {
-			error!(?e, "server error while processing session");
-			return WebError::<&'static str>::internal_server_error().into_response();
-		}
+		None => match session_user_id(&db, &auth, &cookies).await {
+			Ok(u) => u,
+			Err(e) => return e.into_response(),
+		},
 	};
 
 	req.extensions_mut().insert(user_id);
@@ -118,6 +140,28 @@ pub async fn auth_middleware(
 	next.run(req).await
 }
 
+async fn session_user_id(
+	db: &PgPool,
+	auth: &Authenticator,
+	cookies: &CookieJar,
+) -> Result<UserId, WebError<&'static str>> {
+	let session_id = extract_session_id(cookies)?;
+
+	match auth.verify(db, session_id).await {
+		Ok(o) => match o {
+			Ok(u) => Ok(u),
+			Err(AuthenticatorError::NotAuthorized) => {
+				Err(AuthError::SessionIncompleteOrExpired.into())
+			}
+			Err(AuthenticatorError::InvalidSession) => Err(AuthError::SessionInvalid.into()),
+		},
+		Err(e) => {
+			error!(?e, "server error while processing session");
+			Err(WebError::internal_server_error())
+		}
+	}
+}
+
 // TODO: maybe skip the allocation by accessing the Arc<str> inside the user_id directly
 pub async fn user_id(Extension(user_id): Extension<UserId>) -> WebResult<String, &'static str> {
 	Ok::<_, WebError<&'static str>>(user_id.as_str().to_owned().into())
@@ -0,0 +1,43 @@
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::Extension;
+
+use chrono::Utc;
+use itertools::Itertools;
+
+use crate::api::logic::calendar::render_calendar;
+use crate::api::logic::check_object_belong_to_userid;
+use crate::api::util::prelude::*;
+use crate::api::AppState;
+use crate::auth::UserId;
+use crate::db::queries::entry::get_entries_by_user;
+use crate::db::queries::timeslot::get_timeslots;
+
+// Not wrapped in `WebSuccess`, since calendar apps expect a plain iCalendar file.
+pub async fn ics(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+) -> Result<impl IntoResponse, WebError<&'static str>> {
+	let timeslots = get_timeslots(&db, &u, true).await?;
+
+	check_object_belong_to_userid(timeslots.iter(), &u)?;
+
+	let entries = get_entries_by_user(&db, &u).await?;
+
+	check_object_belong_to_userid(entries.iter(), &u)?;
+
+	let entries = entries.into_iter().into_group_map_by(|e| e.timeslot_id);
+
+	let calendar = render_calendar(
+		timeslots
+			.iter()
+			.map(|ts| (ts, entries.get(&ts.id).map_or(&[][..], Vec::as_slice))),
+		Utc::now(),
+	);
+
+	Ok((
+		[(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+		calendar,
+	))
+}
@@ -1,26 +1,41 @@
-use axum::extract::{Json, Path, State};
+use std::collections::HashMap;
+
+use anyhow::Context;
+
+use axum::extract::{Json, Path, Query, State};
 use axum::http::StatusCode;
 use axum::Extension;
 
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, NaiveDate};
 
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
 use uuid::Uuid;
 
+use tower_request_id::RequestId;
 use tracing::{debug, error};
 
 use crate::api::logic::check_object_belong_to_userid;
-use crate::api::logic::entry::{get_time_from_index_and_timeslot, missing_entries, verify_state};
+use crate::api::logic::entry::{
+	get_time_from_index_and_timeslot, missing_entries, missing_entries_timeslots, verify_state,
+};
+use crate::api::logic::entry_import::{
+	find_timeslot, parse_date, parse_state, parse_students, CsvRow, RowError,
+};
+use crate::api::logic::timeslot::get_index_range_timeslot;
 use crate::api::util::prelude::*;
 use crate::api::AppState;
 use crate::auth::UserId;
-use crate::db::model::{Entry, EntryState, Student, StudentState, WebEntry, WebTimeSlot};
+use crate::db::model::{
+	convert_entry, Entry, EntryState, Student, StudentState, WebEntry, WebTimeSlot,
+};
+use crate::db::queries::audit::{log_entry, AuditAction, AuditContext};
 use crate::db::queries::entry::{
-	delete_entry_by_id, get_entries_by_timeslot_id, insert_entry, InsertEntryError,
+	delete_entry_by_id, get_entries_for_occurrences, get_entries_page, insert_entry,
+	restore_entry_by_id, InsertEntryError,
 };
-use crate::db::queries::timeslot::get_timeslot_by_id;
+use crate::db::queries::timeslot::{get_timeslot_by_id, get_timeslots};
 
 use super::logic::entry::next_entry_timeslot;
 
@@ -56,6 +71,7 @@ pub async fn create(
 	State(AppState { db, .. }): State<AppState>,
 	Path(q): Path<MissingQuery>,
 	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
 	Json(r): Json<CreateEntry>,
 ) -> WebResult<&'static str, Value> {
 	let selected_timeslot = match get_timeslot_by_id(&db, &u, q.id).await? {
@@ -83,12 +99,18 @@ pub async fn create(
 			students: r.students,
 		},
 		Err(s) => {
-			debug!("request contained invalid students");
+			debug!(invalid_students=?s, "request contained invalid students");
 			return Err(CreateEntryError::InvalidStudents(s))?;
 		}
 	};
 
-	match insert_entry(&db, entry).await {
+	let snapshot = convert_entry(entry.clone()).context("invalid entry")?;
+
+	let audit = AuditContext::new(&u, &request_id);
+
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	match insert_entry(&mut *tx, entry).await {
 		Ok(()) => (),
 		Err(e) => match e {
 			InsertEntryError::Duplicate => {
@@ -100,6 +122,10 @@ pub async fn create(
 		},
 	};
 
+	log_entry(&mut *tx, &audit, AuditAction::Create, None, Some(&snapshot)).await?;
+
+	tx.commit().await.context("couldn't commit transaction")?;
+
 	Ok((StatusCode::CREATED, "success").into())
 }
 
@@ -108,8 +134,16 @@ pub struct EntryQuery {
 	pub id: Uuid,
 }
 
+#[derive(Deserialize)]
+pub struct PageQuery {
+	limit: Option<u32>,
+	// Index of the last entry of the previous page
+	before: Option<u32>,
+}
+
 pub enum TimeslotQueryError {
 	TimeslotNotFound,
+	InvalidLimit,
 }
 
 #[allow(clippy::from_over_into)]
@@ -118,6 +152,7 @@ impl From<TimeslotQueryError> for WebError<&'static str> {
 		use TimeslotQueryError::*;
 		match v {
 			TimeslotNotFound => (StatusCode::NOT_FOUND, "timeslot not found").into(),
+			InvalidLimit => (StatusCode::UNPROCESSABLE_ENTITY, "limit must be positive").into(),
 		}
 	}
 }
@@ -131,14 +166,23 @@ pub struct QueryReturn {
 pub async fn query(
 	State(AppState { db, .. }): State<AppState>,
 	Path(q): Path<EntryQuery>,
+	Query(page): Query<PageQuery>,
 	Extension(u): Extension<UserId>,
 ) -> WebResult<Vec<QueryReturn>, &'static str> {
+	if page.limit == Some(0) {
+		return Err(TimeslotQueryError::InvalidLimit)?;
+	}
+
 	let timeslot: WebTimeSlot = match get_timeslot_by_id(&db, &u, q.id).await? {
 		Some(x) => x,
 		None => return Err(TimeslotQueryError::TimeslotNotFound)?,
 	};
 
-	let mut res: Vec<_> = get_entries_by_timeslot_id(&db, &u, timeslot.id).await?
+	let before = page.before.map(i32::try_from).transpose()?;
+
+	let (entries, next) = get_entries_page(&db, &u, timeslot.id, before, page.limit).await?;
+
+	let res: Vec<_> = entries
 		.into_iter()
 		.filter_map(|entry| {
 			let Some(timestamp) = get_time_from_index_and_timeslot(&timeslot, entry.index).map(|v| v.fixed_offset()) else {
@@ -150,10 +194,95 @@ pub async fn query(
 		})
 		.collect::<Vec<_>>();
 
-	res.sort_unstable_by(|a, b| b.entry.index.cmp(&a.entry.index));
-
 	check_object_belong_to_userid(res.iter().map(|v| &v.entry), &u)?;
 
+	Ok(WebSuccess::from(res).with_next(next))
+}
+
+#[derive(Deserialize)]
+pub struct EntryRangeQuery {
+	// Both are inclusive
+	from: NaiveDate,
+	to: NaiveDate,
+	state: Option<EntryState>,
+	student: Option<String>,
+}
+
+pub enum EntryRangeError {
+	InvalidDateRange,
+}
+
+impl From<EntryRangeError> for WebError<&'static str> {
+	fn from(v: EntryRangeError) -> WebError<&'static str> {
+		use EntryRangeError::*;
+		match v {
+			InvalidDateRange => {
+				(StatusCode::UNPROCESSABLE_ENTITY, "from should be before to").into()
+			}
+		}
+	}
+}
+
+// Entries of all timeslots, which took place between `from` and `to`.
+pub async fn query_range(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Query(q): Query<EntryRangeQuery>,
+) -> WebResult<Vec<QueryReturn>, &'static str> {
+	if q.from > q.to {
+		return Err(EntryRangeError::InvalidDateRange)?;
+	}
+
+	let timeslots = get_timeslots(&db, &u, true).await?;
+
+	check_object_belong_to_userid(timeslots.iter(), &u)?;
+
+	let mut timeslot_ids = Vec::new();
+	let mut indexes = Vec::new();
+
+	for ts in &timeslots {
+		let Some(range) = get_index_range_timeslot(ts, q.from..q.to) else {
+			continue;
+		};
+
+		for index in range.start..=range.end {
+			timeslot_ids.push(ts.id);
+			indexes.push(index.try_into()?);
+		}
+	}
+
+	let entries = get_entries_for_occurrences(&db, &u, &timeslot_ids, &indexes).await?;
+
+	check_object_belong_to_userid(entries.iter(), &u)?;
+
+	let timeslots: HashMap<_, _> = timeslots.iter().map(|ts| (ts.id, ts)).collect();
+
+	// Student names are encrypted in the database, so they can only be filtered here.
+	let mut res: Vec<_> = entries
+		.into_iter()
+		.filter(|e| q.state.is_none_or(|s| s == e.state))
+		.filter(|e| {
+			q.student
+				.as_ref()
+				.is_none_or(|name| e.students.iter().any(|s| &s.student == name))
+		})
+		.filter_map(|entry| {
+			let ts = timeslots.get(&entry.timeslot_id)?;
+
+			let Some(timestamp) = get_time_from_index_and_timeslot(ts, entry.index) else {
+				error!(timeslot=%entry.timeslot_id, index=%entry.index, "date of entry in database overflows the chrono limits");
+				return None;
+			};
+
+			Some(QueryReturn {
+				entry,
+				timestamp: timestamp.fixed_offset(),
+			})
+		})
+		.collect();
+
+	res.sort_unstable_by_key(|r| r.timestamp);
+
 	Ok(res.into())
 }
 
@@ -195,6 +324,36 @@ pub async fn missing(
 	Ok(missing_entries(&db, &u, &timeslot).await?.into())
 }
 
+#[derive(Serialize)]
+pub struct MissingForTimeslot {
+	timeslot_id: Uuid,
+	subject: String,
+	missing: Vec<UnfilledEntry>,
+}
+
+// Missing entries of all timeslots, which aren't archived.
+pub async fn missing_all(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+) -> WebResult<Vec<MissingForTimeslot>, &'static str> {
+	let timeslots = get_timeslots(&db, &u, false).await?;
+
+	check_object_belong_to_userid(timeslots.iter(), &u)?;
+
+	let mut missing = missing_entries_timeslots(&db, &u, &timeslots).await?;
+
+	let res: Vec<_> = timeslots
+		.into_iter()
+		.map(|ts| MissingForTimeslot {
+			timeslot_id: ts.id,
+			missing: missing.remove(&ts.id).unwrap_or_default(),
+			subject: ts.subject,
+		})
+		.collect();
+
+	Ok(res.into())
+}
+
 #[derive(Deserialize)]
 pub struct NextQuery {
 	pub id: Uuid,
@@ -244,8 +403,178 @@ pub async fn delete(
 	State(AppState { db, .. }): State<AppState>,
 	Path(q): Path<DeleteQuery>,
 	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
 ) -> WebResult<&'static str, &'static str> {
-	delete_entry_by_id(&db, &u, q.id, q.index.try_into()?).await?;
+	let audit = AuditContext::new(&u, &request_id);
+
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	if let Some(e) = delete_entry_by_id(&mut *tx, &u, q.id, q.index.try_into()?).await? {
+		log_entry(&mut *tx, &audit, AuditAction::Delete, Some(&e), None).await?;
+	}
+
+	tx.commit().await.context("couldn't commit transaction")?;
 
 	Ok("success".into())
 }
+
+pub enum RestoreError {
+	TimeslotNotFound,
+	NotFound,
+	DuplicateIndex,
+}
+
+impl From<RestoreError> for WebError<&'static str> {
+	fn from(v: RestoreError) -> WebError<&'static str> {
+		use RestoreError::*;
+		match v {
+			TimeslotNotFound => (StatusCode::NOT_FOUND, "timeslot not found").into(),
+			NotFound => (StatusCode::NOT_FOUND, "couldn't find deleted entry").into(),
+			DuplicateIndex => (StatusCode::CONFLICT, "duplicate index").into(),
+		}
+	}
+}
+
+// Entries of a deleted timeslot are restored together with the timeslot.
+pub async fn restore(
+	State(AppState { db, .. }): State<AppState>,
+	Path(q): Path<DeleteQuery>,
+	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+) -> WebResult<&'static str, &'static str> {
+	if get_timeslot_by_id(&db, &u, q.id).await?.is_none() {
+		return Err(RestoreError::TimeslotNotFound)?;
+	}
+
+	let audit = AuditContext::new(&u, &request_id);
+
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	let entry = match restore_entry_by_id(&mut *tx, &u, q.id, q.index.try_into()?).await {
+		Ok(Some(e)) => e,
+		Ok(None) => return Err(RestoreError::NotFound)?,
+		Err(InsertEntryError::Duplicate) => return Err(RestoreError::DuplicateIndex)?,
+		Err(InsertEntryError::Other(e)) => Err(e)?,
+	};
+
+	log_entry(&mut *tx, &audit, AuditAction::Restore, None, Some(&entry)).await?;
+
+	tx.commit().await.context("couldn't commit transaction")?;
+
+	Ok("restored".into())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportRowStatus {
+	Success,
+	Duplicate,
+	Error,
+}
+
+#[derive(Serialize)]
+pub struct ImportRowReport {
+	// Line in the csv file, the header is line 1.
+	row: usize,
+	status: ImportRowStatus,
+	timeslot_id: Option<Uuid>,
+	index: Option<u32>,
+	error: Option<String>,
+}
+
+impl ImportRowReport {
+	fn error(row: usize, e: &RowError) -> ImportRowReport {
+		ImportRowReport {
+			row,
+			status: ImportRowStatus::Error,
+			timeslot_id: None,
+			index: None,
+			error: Some(e.to_string()),
+		}
+	}
+}
+
+fn parse_row(timeslots: &[WebTimeSlot], u: &UserId, row: &CsvRow) -> Result<Entry, RowError> {
+	let date = parse_date(&row.date)?;
+	let state = parse_state(&row.state)?;
+	let students = parse_students(&row.students)?;
+
+	let (ts, index) = find_timeslot(timeslots, &row.subject, date)?;
+
+	if verify_state(state, &students, &ts.students).is_err() {
+		return Err(RowError::InvalidStudents);
+	}
+
+	Ok(Entry {
+		user_id: u.as_str().to_owned(),
+		// Indices are always smaller than i32::MAX, since they are derived from dates.
+		index: index.try_into().map_err(|_| RowError::InvalidDate)?,
+		timeslot_id: ts.id,
+		state_enum: state,
+		students,
+	})
+}
+
+// Every row is imported on its own, so one invalid row doesn't prevent importing the rest.
+pub async fn import(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+	body: String,
+) -> WebResult<Vec<ImportRowReport>, &'static str> {
+	let timeslots = get_timeslots(&db, &u, true).await?;
+
+	let audit = AuditContext::new(&u, &request_id);
+
+	let mut reader = csv::ReaderBuilder::new()
+		.trim(csv::Trim::All)
+		.from_reader(body.as_bytes());
+
+	let mut report = Vec::new();
+
+	for (i, record) in reader.deserialize::<CsvRow>().enumerate() {
+		let row = i + 2;
+
+		let entry = match record
+			.map_err(|e| RowError::InvalidRow(e.to_string()))
+			.and_then(|r| parse_row(&timeslots, &u, &r))
+		{
+			Ok(e) => e,
+			Err(e) => {
+				// Not logging the error itself, since it can contain student names.
+				debug!(row, "invalid row in entry import");
+				report.push(ImportRowReport::error(row, &e));
+				continue;
+			}
+		};
+
+		let timeslot_id = Some(entry.timeslot_id);
+		let index = Some(entry.index.try_into()?);
+
+		let snapshot = convert_entry(entry.clone()).context("invalid entry")?;
+
+		let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+		// The transaction is rolled back on drop, if the entry is a duplicate.
+		let status = match insert_entry(&mut *tx, entry).await {
+			Ok(()) => {
+				log_entry(&mut *tx, &audit, AuditAction::Create, None, Some(&snapshot)).await?;
+				tx.commit().await.context("couldn't commit transaction")?;
+
+				ImportRowStatus::Success
+			}
+			Err(InsertEntryError::Duplicate) => ImportRowStatus::Duplicate,
+			Err(InsertEntryError::Other(e)) => Err(e)?,
+		};
+
+		report.push(ImportRowReport {
+			row,
+			status,
+			timeslot_id,
+			index,
+			error: None,
+		});
+	}
+
+	Ok(report.into())
+}
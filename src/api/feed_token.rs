@@ -0,0 +1,70 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Extension;
+
+use serde::Serialize;
+
+use crate::api::util::prelude::*;
+use crate::api::AppState;
+use crate::auth::{gen_feed_token, hash_feed_token, UserId};
+use crate::db::queries::feed_token::{self, InsertFeedTokenError};
+
+#[derive(Serialize)]
+pub struct FeedTokenReturn {
+	token: String,
+}
+
+pub enum FeedTokenError {
+	AlreadyExists,
+	NotFound,
+}
+
+impl From<FeedTokenError> for WebError<&'static str> {
+	fn from(v: FeedTokenError) -> WebError<&'static str> {
+		use FeedTokenError::*;
+		match v {
+			AlreadyExists => (StatusCode::CONFLICT, "feed token already exists").into(),
+			NotFound => (StatusCode::NOT_FOUND, "feed token not found").into(),
+		}
+	}
+}
+
+// Only the hash is stored, so this is the only time the token is returned.
+pub async fn create(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+) -> WebResult<FeedTokenReturn, &'static str> {
+	let token = gen_feed_token();
+
+	match feed_token::insert(&db, &u, &hash_feed_token(&token)).await {
+		Ok(()) => (),
+		Err(InsertFeedTokenError::Duplicate) => return Err(FeedTokenError::AlreadyExists)?,
+		Err(InsertFeedTokenError::Other(e)) => Err(e)?,
+	}
+
+	Ok((StatusCode::CREATED, FeedTokenReturn { token }).into())
+}
+
+pub async fn rotate(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+) -> WebResult<FeedTokenReturn, &'static str> {
+	let token = gen_feed_token();
+
+	if feed_token::replace(&db, &u, &hash_feed_token(&token)).await? == 0 {
+		return Err(FeedTokenError::NotFound)?;
+	}
+
+	Ok(FeedTokenReturn { token }.into())
+}
+
+pub async fn revoke(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+) -> WebResult<&'static str, &'static str> {
+	if feed_token::delete(&db, &u).await? == 0 {
+		return Err(FeedTokenError::NotFound)?;
+	}
+
+	Ok("revoked".into())
+}
@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::logic::entry::{get_time_from_index_and_timeslot, verify_state};
+use crate::api::logic::timeslot::TimeslotCreate;
+use crate::db::model::{EntryState, Student, StudentState, WebEntry, WebTimeSlot};
+
+pub const BACKUP_VERSION: u32 = 1;
+
+// User ids aren't included, so backups can be restored on other deployments.
+// Hourly rates are the only settings and are stored with their timeslot.
+#[derive(Serialize, Deserialize)]
+pub struct AccountBackup {
+	pub version: u32,
+	pub exported: DateTime<Utc>,
+	pub timeslots: Vec<BackupTimeslot>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BackupTimeslot {
+	pub id: Uuid,
+	pub subject: String,
+	pub students: Vec<String>,
+	pub time: Range<NaiveTime>,
+	pub timerange: Range<NaiveDate>,
+	pub timezone: Tz,
+	pub hourly_rate: Option<u32>,
+	pub entries: Vec<BackupEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BackupEntry {
+	pub index: u32,
+	pub state: EntryState,
+	pub students: Vec<StudentState>,
+}
+
+impl BackupTimeslot {
+	pub fn new(ts: WebTimeSlot, entries: Vec<WebEntry>) -> BackupTimeslot {
+		BackupTimeslot {
+			id: ts.id,
+			subject: ts.subject,
+			students: ts.students,
+			time: ts.time,
+			timerange: ts.timerange,
+			timezone: ts.timezone,
+			hourly_rate: ts.hourly_rate,
+			entries: entries
+				.into_iter()
+				.map(|e| BackupEntry {
+					index: e.index,
+					state: e.state,
+					students: e.students,
+				})
+				.collect(),
+		}
+	}
+
+	pub fn to_create(&self) -> TimeslotCreate {
+		TimeslotCreate {
+			students: self
+				.students
+				.iter()
+				.map(|name| Student { name: name.clone() })
+				.collect(),
+			subject: self.subject.clone(),
+			weekday: self.timerange.start.weekday(),
+			time: self.time.clone(),
+			timerange: self.timerange.clone(),
+			timezone: self.timezone,
+			hourly_rate: self.hourly_rate,
+		}
+	}
+
+	// Backups might have been edited by hand, so they get the same checks as new data.
+	pub fn validate(&self) -> Result<(), String> {
+		let create = self.to_create();
+
+		if let Err(e) = create.validate() {
+			return Err(format!("timeslot {}: {}", self.id, e.message()));
+		}
+
+		// Only used to calculate the occurrences of the entries.
+		let ts = WebTimeSlot {
+			user_id: String::new(),
+			id: self.id,
+			subject: create.subject,
+			students: self.students.clone(),
+			time: create.time,
+			timerange: create.timerange,
+			weekday: create.weekday,
+			timezone: create.timezone,
+			hourly_rate: create.hourly_rate,
+			archived: false,
+		};
+
+		let mut indices = HashSet::with_capacity(self.entries.len());
+
+		for e in &self.entries {
+			if !indices.insert(e.index) {
+				return Err(format!("entry {}-{}: duplicate index", self.id, e.index));
+			}
+
+			// Checked before calculating the occurrence, since `7 * index` overflows for large indices.
+			// Indices are stored as i32.
+			if i64::from(e.index) > (ts.timerange.end - ts.timerange.start).num_weeks()
+				|| i32::try_from(e.index).is_err()
+				|| get_time_from_index_and_timeslot(&ts, e.index).is_none()
+			{
+				return Err(format!("entry {}-{}: index out of range", self.id, e.index));
+			}
+
+			if verify_state(e.state, &e.students, &self.students).is_err() {
+				return Err(format!("entry {}-{}: invalid students", self.id, e.index));
+			}
+		}
+
+		Ok(())
+	}
+
+	// `validate` only checks the students of the entries against the backup,
+	// entries merged into an existing timeslot also have to match its students.
+	pub fn validate_merge(&self, ts: &WebTimeSlot) -> Result<(), String> {
+		for e in &self.entries {
+			if verify_state(e.state, &e.students, &ts.students).is_err() {
+				return Err(format!(
+					"entry {}-{}: invalid students for timeslot {}",
+					self.id, e.index, ts.id
+				));
+			}
+		}
+
+		Ok(())
+	}
+
+	pub fn conflicts_with(&self, ts: &WebTimeSlot) -> bool {
+		self.subject == ts.subject
+			&& self.time == ts.time
+			&& self.timerange == ts.timerange
+			&& self.timezone == ts.timezone
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::db::model::test::timeslot;
+	use crate::db::model::{EntryState, StudentState, StudentStatus, WebTimeSlot};
+
+	use super::{BackupEntry, BackupTimeslot};
+
+	fn backup(students: &[&str]) -> BackupTimeslot {
+		let ts = timeslot();
+
+		BackupTimeslot {
+			id: ts.id,
+			subject: ts.subject,
+			students: students.iter().map(|&s| s.into()).collect(),
+			time: ts.time,
+			timerange: ts.timerange,
+			timezone: ts.timezone,
+			hourly_rate: ts.hourly_rate,
+			entries: vec![BackupEntry {
+				index: 0,
+				state: EntryState::Success,
+				students: students
+					.iter()
+					.map(|&s| StudentState {
+						student: s.into(),
+						status: StudentStatus::Present,
+					})
+					.collect(),
+			}],
+		}
+	}
+
+	fn existing(students: &[&str]) -> WebTimeSlot {
+		WebTimeSlot {
+			students: students.iter().map(|&s| s.into()).collect(),
+			..timeslot()
+		}
+	}
+
+	#[test]
+	fn test_validate_merge() {
+		let b = backup(&["a", "b"]);
+		assert!(b.validate().is_ok());
+
+		assert!(b.validate_merge(&existing(&["a", "b"])).is_ok());
+		// Students of the backup aren't part of the existing timeslot
+		assert!(b.validate_merge(&existing(&["a", "c"])).is_err());
+		// Not every student of the existing timeslot has a state
+		assert!(b.validate_merge(&existing(&["a", "b", "c"])).is_err());
+	}
+
+	#[test]
+	fn test_validate_index() {
+		let mut b = backup(&["a"]);
+
+		// Mondays from 2024-01-01 to 2024-03-25
+		b.entries[0].index = 12;
+		assert!(b.validate().is_ok());
+
+		for index in [13, 613_566_757, u32::MAX] {
+			b.entries[0].index = index;
+			assert!(b.validate().is_err());
+		}
+	}
+}
@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{
+	DateTime, Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use itertools::Itertools;
+
+use crate::api::logic::entry::get_time_from_index_and_timeslot;
+use crate::api::logic::timeslot::TimeslotCreate;
+use crate::db::model::{EntryState, Student, WebEntry, WebTimeSlot};
+use crate::util::resolve_local_time;
+
+const LOCAL_FORMAT: &str = "%Y%m%dT%H%M%S";
+const UTC_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+// Lines longer than this have to be folded (RFC 5545 3.1)
+const MAX_LINE_OCTETS: usize = 75;
+
+// Occurrences with these states didn't take place, so they're excluded from the calendar.
+fn is_excluded(state: EntryState) -> bool {
+	matches!(
+		state,
+		EntryState::Holidays | EntryState::CancelledByStudents | EntryState::CancelledByTutor
+	)
+}
+
+fn byday(weekday: Weekday) -> &'static str {
+	match weekday {
+		Weekday::Mon => "MO",
+		Weekday::Tue => "TU",
+		Weekday::Wed => "WE",
+		Weekday::Thu => "TH",
+		Weekday::Fri => "FR",
+		Weekday::Sat => "SA",
+		Weekday::Sun => "SU",
+	}
+}
+
+fn escape_text(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+
+	for c in text.chars() {
+		match c {
+			'\\' => escaped.push_str("\\\\"),
+			';' => escaped.push_str("\\;"),
+			',' => escaped.push_str("\\,"),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => (),
+			c => escaped.push(c),
+		}
+	}
+
+	escaped
+}
+
+fn write_line(output: &mut String, line: &str) {
+	let mut octets = 0;
+
+	for c in line.chars() {
+		if octets + c.len_utf8() > MAX_LINE_OCTETS {
+			// Continuation lines start with a single space, which counts towards the limit.
+			output.push_str("\r\n ");
+			octets = 1;
+		}
+
+		output.push(c);
+		octets += c.len_utf8();
+	}
+
+	output.push_str("\r\n");
+}
+
+fn format_local(time: NaiveDateTime) -> String {
+	time.format(LOCAL_FORMAT).to_string()
+}
+
+fn until(ts: &WebTimeSlot) -> String {
+	// UNTIL has to be in UTC, if DTSTART has a TZID.
+	let last = ts
+		.timerange
+		.end
+		.and_time(NaiveTime::from_hms_opt(23, 59, 59).expect("valid time"));
+
+	match resolve_local_time(last, ts.timezone) {
+		Some(t) => t.with_timezone(&Utc).format(UTC_FORMAT).to_string(),
+		// Only happens at the limits of chrono, a floating time is still interpreted in the TZID of DTSTART.
+		None => format_local(last),
+	}
+}
+
+fn write_event(output: &mut String, ts: &WebTimeSlot, entries: &[WebEntry], now: DateTime<Utc>) {
+	let tzid = ts.timezone.name();
+
+	write_line(output, "BEGIN:VEVENT");
+	write_line(output, &format!("UID:{}@lernlotsen", ts.id));
+	write_line(output, &format!("DTSTAMP:{}", now.format(UTC_FORMAT)));
+	write_line(
+		output,
+		&format!(
+			"DTSTART;TZID={tzid}:{}",
+			format_local(ts.timerange.start.and_time(ts.time.start))
+		),
+	);
+	write_line(
+		output,
+		&format!(
+			"DTEND;TZID={tzid}:{}",
+			format_local(ts.timerange.start.and_time(ts.time.end))
+		),
+	);
+	write_line(
+		output,
+		&format!(
+			"RRULE:FREQ=WEEKLY;BYDAY={};UNTIL={}",
+			byday(ts.weekday),
+			until(ts)
+		),
+	);
+
+	let exdates = entries
+		.iter()
+		.filter(|e| is_excluded(e.state))
+		.filter_map(|e| get_time_from_index_and_timeslot(ts, e.index))
+		.map(|t| format_local(t.naive_local()))
+		.join(",");
+
+	if !exdates.is_empty() {
+		write_line(output, &format!("EXDATE;TZID={tzid}:{exdates}"));
+	}
+
+	write_line(output, &format!("SUMMARY:{}", escape_text(&ts.subject)));
+	write_line(
+		output,
+		&format!("DESCRIPTION:{}", escape_text(&ts.students.join(", "))),
+	);
+	write_line(output, "END:VEVENT");
+}
+
+// Calendar apps resolve the IANA names in TZID themselves, so no VTIMEZONE components are emitted.
+pub fn render_calendar<'a>(
+	timeslots: impl Iterator<Item = (&'a WebTimeSlot, &'a [WebEntry])>,
+	now: DateTime<Utc>,
+) -> String {
+	let mut output = String::new();
+
+	write_line(&mut output, "BEGIN:VCALENDAR");
+	write_line(&mut output, "VERSION:2.0");
+	write_line(&mut output, "PRODID:-//lernlotsen//backend//EN");
+	write_line(&mut output, "CALSCALE:GREGORIAN");
+
+	for (ts, entries) in timeslots {
+		write_event(&mut output, ts, entries, now);
+	}
+
+	write_line(&mut output, "END:VCALENDAR");
+
+	output
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+	#[error("missing {0}")]
+	MissingProperty(&'static str),
+	#[error("invalid {0}")]
+	InvalidProperty(&'static str),
+	#[error("unknown timezone: {0}")]
+	UnknownTimezone(String),
+	#[error("only weekly recurring events without an interval are supported")]
+	UnsupportedRecurrence,
+	#[error("events without a timezone are not supported")]
+	FloatingTime,
+	#[error("events spanning multiple days are not supported")]
+	MultipleDays,
+}
+
+pub struct ImportedEvent {
+	pub uid: Option<String>,
+	pub result: Result<TimeslotCreate, ImportError>,
+}
+
+struct Property {
+	name: String,
+	params: HashMap<String, String>,
+	value: String,
+}
+
+impl Property {
+	fn param(&self, name: &str) -> Option<&str> {
+		self.params.get(name).map(|p| p.trim_matches('"'))
+	}
+}
+
+fn unfold(input: &str) -> Vec<String> {
+	let mut lines: Vec<String> = Vec::new();
+
+	for line in input.split('\n') {
+		let line = line.strip_suffix('\r').unwrap_or(line);
+
+		match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+			(Some(continuation), Some(last)) => last.push_str(continuation),
+			_ => lines.push(line.to_string()),
+		}
+	}
+
+	lines
+}
+
+fn parse_property(line: &str) -> Option<Property> {
+	// Parameter values may contain colons, if they are quoted.
+	let mut in_quotes = false;
+	let colon = line.char_indices().find_map(|(i, c)| match c {
+		'"' => {
+			in_quotes = !in_quotes;
+			None
+		}
+		':' if !in_quotes => Some(i),
+		_ => None,
+	})?;
+
+	let (head, value) = (&line[..colon], &line[colon + 1..]);
+
+	let mut parts = head.split(';');
+	let name = parts.next()?.to_ascii_uppercase();
+	let params = parts
+		.filter_map(|p| p.split_once('='))
+		.map(|(k, v)| (k.to_ascii_uppercase(), v.to_string()))
+		.collect();
+
+	Some(Property {
+		name,
+		params,
+		value: value.to_string(),
+	})
+}
+
+fn unescape_text(text: &str) -> String {
+	let mut unescaped = String::with_capacity(text.len());
+	let mut chars = text.chars();
+
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			unescaped.push(c);
+			continue;
+		}
+
+		match chars.next() {
+			Some('n' | 'N') => unescaped.push('\n'),
+			Some(c) => unescaped.push(c),
+			None => (),
+		}
+	}
+
+	unescaped
+}
+
+fn parse_date_time(prop: &Property, name: &'static str) -> Result<DateTime<Tz>, ImportError> {
+	if prop.param("VALUE") == Some("DATE") {
+		return Err(ImportError::InvalidProperty(name));
+	}
+
+	let (value, timezone) = match (prop.param("TZID"), prop.value.strip_suffix('Z')) {
+		(Some(tzid), _) => (
+			prop.value.as_str(),
+			Tz::from_str(tzid).map_err(|_| ImportError::UnknownTimezone(tzid.to_string()))?,
+		),
+		(None, Some(value)) => (value, Tz::UTC),
+		(None, None) => return Err(ImportError::FloatingTime),
+	};
+
+	let naive = NaiveDateTime::parse_from_str(value, LOCAL_FORMAT)
+		.map_err(|_| ImportError::InvalidProperty(name))?;
+
+	resolve_local_time(naive, timezone).ok_or(ImportError::InvalidProperty(name))
+}
+
+fn parse_until(value: &str, timezone: Tz) -> Result<NaiveDate, ImportError> {
+	if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+		return Ok(date);
+	}
+
+	match value.strip_suffix('Z') {
+		Some(utc) => NaiveDateTime::parse_from_str(utc, LOCAL_FORMAT).map(|t| {
+			Utc.from_utc_datetime(&t)
+				.with_timezone(&timezone)
+				.date_naive()
+		}),
+		None => NaiveDateTime::parse_from_str(value, LOCAL_FORMAT).map(|t| t.date()),
+	}
+	.map_err(|_| ImportError::InvalidProperty("UNTIL"))
+}
+
+fn parse_rrule(value: &str, start: DateTime<Tz>) -> Result<NaiveDate, ImportError> {
+	let rule: HashMap<_, _> = value
+		.split(';')
+		.filter_map(|p| p.split_once('='))
+		.map(|(k, v)| (k.to_ascii_uppercase(), v))
+		.collect();
+
+	if rule.get("FREQ") != Some(&"WEEKLY") {
+		return Err(ImportError::UnsupportedRecurrence);
+	}
+
+	if rule.get("INTERVAL").is_some_and(|i| *i != "1") {
+		return Err(ImportError::UnsupportedRecurrence);
+	}
+
+	if rule
+		.get("BYDAY")
+		.is_some_and(|d| *d != byday(start.weekday()))
+	{
+		return Err(ImportError::UnsupportedRecurrence);
+	}
+
+	match (rule.get("UNTIL"), rule.get("COUNT")) {
+		(Some(until), _) => parse_until(until, start.timezone()),
+		(None, Some(count)) => {
+			let count: u64 = count
+				.parse()
+				.map_err(|_| ImportError::InvalidProperty("COUNT"))?;
+
+			count
+				.saturating_sub(1)
+				.checked_mul(7)
+				.and_then(|days| start.date_naive().checked_add_days(Days::new(days)))
+				.ok_or(ImportError::InvalidProperty("COUNT"))
+		}
+		(None, None) => Err(ImportError::MissingProperty("UNTIL")),
+	}
+}
+
+fn parse_event(props: &[Property]) -> Result<TimeslotCreate, ImportError> {
+	let get = |name: &'static str| {
+		props
+			.iter()
+			.find(|p| p.name == name)
+			.ok_or(ImportError::MissingProperty(name))
+	};
+
+	let start = parse_date_time(get("DTSTART")?, "DTSTART")?;
+	let end = parse_date_time(get("DTEND")?, "DTEND")?.with_timezone(&start.timezone());
+
+	if start.date_naive() != end.date_naive() {
+		return Err(ImportError::MultipleDays);
+	}
+
+	let until = parse_rrule(&get("RRULE")?.value, start)?;
+
+	let subject = unescape_text(&get("SUMMARY")?.value);
+
+	// Exported calendars list the students in the description.
+	let students = props
+		.iter()
+		.find(|p| p.name == "DESCRIPTION")
+		.map(|p| {
+			unescape_text(&p.value)
+				.split(',')
+				.map(str::trim)
+				.filter(|s| !s.is_empty())
+				.map(|name| Student {
+					name: name.to_string(),
+				})
+				.collect()
+		})
+		.unwrap_or_default();
+
+	Ok(TimeslotCreate {
+		students,
+		subject,
+		weekday: start.weekday(),
+		time: start.time()..end.time(),
+		timerange: start.date_naive()..until,
+		timezone: start.timezone(),
+		hourly_rate: None,
+	})
+}
+
+// Only parses the subset of RFC 5545 needed for weekly recurring events.
+pub fn parse_calendar(input: &str) -> Vec<ImportedEvent> {
+	let mut events = Vec::new();
+
+	// Properties of nested components (like VALARM) are ignored.
+	let mut depth = 0u32;
+	let mut current: Option<Vec<Property>> = None;
+
+	for prop in unfold(input).iter().filter_map(|l| parse_property(l)) {
+		match (prop.name.as_str(), prop.value.to_ascii_uppercase().as_str()) {
+			("BEGIN", "VEVENT") if current.is_none() => {
+				current = Some(Vec::new());
+				depth = 0;
+			}
+			("BEGIN", _) => depth += 1,
+			("END", "VEVENT") if depth == 0 => {
+				if let Some(props) = current.take() {
+					events.push(ImportedEvent {
+						uid: props
+							.iter()
+							.find(|p| p.name == "UID")
+							.map(|p| p.value.clone()),
+						result: parse_event(&props),
+					});
+				}
+			}
+			("END", _) => depth = depth.saturating_sub(1),
+			_ => {
+				if let Some(props) = current.as_mut() {
+					if depth == 0 {
+						props.push(prop);
+					}
+				}
+			}
+		}
+	}
+
+	events
+}
+
+#[cfg(test)]
+mod test {
+	use chrono::{NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+
+	use crate::db::model::test::timeslot;
+	use crate::db::model::{EntryState, WebEntry};
+
+	use super::{escape_text, parse_calendar, render_calendar, write_line};
+
+	#[test]
+	fn test_escape_text() {
+		assert_eq!(escape_text("a, b; c\\d\ne"), r"a\, b\; c\\d\ne");
+	}
+
+	#[test]
+	fn test_write_line_folds() {
+		let mut output = String::new();
+		let line = "ä".repeat(50);
+
+		write_line(&mut output, &line);
+
+		for l in output.split("\r\n") {
+			assert!(l.len() <= 75);
+		}
+		assert_eq!(output.replace("\r\n ", ""), format!("{line}\r\n"));
+	}
+
+	#[test]
+	fn test_render_calendar() {
+		let ts = timeslot();
+		let entry = |index, state| WebEntry {
+			user_id: "hello".into(),
+			index,
+			timeslot_id: ts.id,
+			state,
+			students: Vec::new(),
+		};
+		let entries = [
+			entry(1, EntryState::Holidays),
+			entry(2, EntryState::Success),
+			entry(3, EntryState::CancelledByTutor),
+		];
+		let now = Utc.with_ymd_and_hms(2024, 2, 1, 12, 0, 0).unwrap();
+
+		let calendar = render_calendar([(&ts, entries.as_slice())].into_iter(), now);
+
+		// The last day of the timerange ends at 23:59:59 in Berlin, which is 22:59:59 UTC in winter.
+		assert_eq!(
+			calendar,
+			format!(
+				"BEGIN:VCALENDAR\r\n\
+				VERSION:2.0\r\n\
+				PRODID:-//lernlotsen//backend//EN\r\n\
+				CALSCALE:GREGORIAN\r\n\
+				BEGIN:VEVENT\r\n\
+				UID:{}@lernlotsen\r\n\
+				DTSTAMP:20240201T120000Z\r\n\
+				DTSTART;TZID=Europe/Berlin:20240101T140000\r\n\
+				DTEND;TZID=Europe/Berlin:20240101T150000\r\n\
+				RRULE:FREQ=WEEKLY;BYDAY=MO;UNTIL=20240325T225959Z\r\n\
+				EXDATE;TZID=Europe/Berlin:20240108T140000,20240122T140000\r\n\
+				SUMMARY:math\r\n\
+				DESCRIPTION:a\r\n\
+				END:VEVENT\r\n\
+				END:VCALENDAR\r\n",
+				ts.id
+			)
+		);
+	}
+
+	#[test]
+	fn test_parse_calendar() {
+		let input = "BEGIN:VCALENDAR\r\n\
+			BEGIN:VEVENT\r\n\
+			UID:1\r\n\
+			DTSTART;TZID=Europe/Berlin:20240101T140000\r\n\
+			DTEND;TZID=Europe/Berlin:20240101T150000\r\n\
+			RRULE:FREQ=WEEKLY;BYDAY=MO;UNTIL=20240325T225959Z\r\n\
+			SUMMARY:Ma\r\n \
+			the\r\n\
+			DESCRIPTION:a\\, b\r\n\
+			END:VEVENT\r\n\
+			BEGIN:VEVENT\r\n\
+			UID:2\r\n\
+			DTSTART:20240101T140000\r\n\
+			DTEND:20240101T150000\r\n\
+			RRULE:FREQ=WEEKLY;COUNT=2\r\n\
+			SUMMARY:Floating\r\n\
+			END:VEVENT\r\n\
+			END:VCALENDAR\r\n";
+
+		let events = parse_calendar(input);
+		assert_eq!(events.len(), 2);
+
+		let ts = events[0].result.as_ref().unwrap();
+		assert_eq!(events[0].uid.as_deref(), Some("1"));
+		assert_eq!(ts.subject, "Mathe");
+		assert_eq!(ts.students.len(), 2);
+		assert_eq!(ts.weekday, Weekday::Mon);
+		assert_eq!(ts.timezone, chrono_tz::Europe::Berlin);
+		assert_eq!(
+			ts.time,
+			NaiveTime::from_hms_opt(14, 0, 0).unwrap()..NaiveTime::from_hms_opt(15, 0, 0).unwrap()
+		);
+		assert_eq!(
+			ts.timerange,
+			NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+				..NaiveDate::from_ymd_opt(2024, 3, 25).unwrap()
+		);
+
+		assert!(events[1].result.is_err());
+	}
+
+	#[test]
+	fn test_parse_calendar_count() {
+		let event = |count: &str| {
+			format!(
+				"BEGIN:VCALENDAR\r\n\
+				BEGIN:VEVENT\r\n\
+				DTSTART;TZID=Europe/Berlin:20240101T140000\r\n\
+				DTEND;TZID=Europe/Berlin:20240101T150000\r\n\
+				RRULE:FREQ=WEEKLY;COUNT={count}\r\n\
+				SUMMARY:Mathe\r\n\
+				END:VEVENT\r\n\
+				END:VCALENDAR\r\n"
+			)
+		};
+
+		let events = parse_calendar(&event("3"));
+		assert_eq!(
+			events[0].result.as_ref().unwrap().timerange,
+			NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+				..NaiveDate::from_ymd_opt(2024, 1, 15).unwrap()
+		);
+
+		// Adding the weeks would overflow
+		let events = parse_calendar(&event(&u64::MAX.to_string()));
+		assert!(events[0].result.is_err());
+	}
+}
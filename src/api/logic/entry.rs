@@ -2,18 +2,18 @@ use std::collections::HashMap;
 
 use anyhow::Context;
 
-use chrono::{DateTime, TimeZone, Utc};
-use chrono::{Duration, NaiveDate};
-use chrono_tz::Tz;
-use itertools::Itertools;
+use chrono::NaiveDate;
+use chrono::{DateTime, Utc};
 
 use sqlx::PgPool;
 use tracing::{debug, error, trace, warn};
+use uuid::Uuid;
 
 use crate::api::entry::UnfilledEntry;
 use crate::auth::UserId;
 use crate::db::model::{EntryState, Student, StudentState, StudentStatus, WebTimeSlot};
-use crate::db::queries::entry::get_entries_with_index_in;
+use crate::db::queries::entry::get_missing_occurrences;
+use crate::util::resolve_local_time;
 
 pub fn verify_state(
 	entry_state: EntryState,
@@ -140,24 +140,28 @@ pub fn get_time_from_index_and_timeslot(
 
 	let time = new_date.and_time(timeslot.time.start);
 
-	let local_time = time.and_local_timezone(timeslot.timezone);
-
-	// This is cleaner.
-	// Also this attribute isn't useless.
-	#[allow(clippy::useless_attribute)]
-	#[allow(clippy::items_after_statements)]
-	use chrono::LocalResult::{Ambiguous, None, Single};
-	match local_time {
-		None => {
-			warn!(%time, "could not convert date to local");
-			Option::None
-		}
-		Single(t) => Some(t),
-		Ambiguous(s, e) => {
-			warn!(%time, start=%s, end=%e, "time in local timezone is ambiguous");
-			Option::None
-		}
+	let local_time = resolve_local_time(time, timeslot.timezone);
+
+	if local_time.is_none() {
+		warn!(%time, "could not convert date to local");
+	}
+
+	local_time
+}
+
+// Inverse of `get_time_from_index_and_timeslot`, `None` if no occurrence of the timeslot is on `date`.
+pub fn get_index_from_date_and_timeslot(timeslot: &WebTimeSlot, date: NaiveDate) -> Option<u32> {
+	if date < timeslot.timerange.start || date > timeslot.timerange.end {
+		return None;
 	}
+
+	let days = (date - timeslot.timerange.start).num_days();
+
+	if days % 7 != 0 {
+		return None;
+	}
+
+	(days / 7).try_into().ok()
 }
 
 pub async fn missing_entries(
@@ -165,72 +169,64 @@ pub async fn missing_entries(
 	u: &UserId,
 	timeslot: &WebTimeSlot,
 ) -> anyhow::Result<Vec<UnfilledEntry>> {
-	let mut required_entries = get_entries(timeslot).enumerate().collect::<HashMap<_, _>>();
-
-	debug!(
-		?required_entries,
-		"calculated required entries for timeslot"
-	);
+	let mut missing = missing_entries_timeslots(db, u, std::slice::from_ref(timeslot)).await?;
 
-	// Entry indices are always u32 or smaller.
-	let required_indexes = required_entries
-		.keys()
-		.map(|x| (*x).try_into())
-		.try_collect()?;
-
-	let found_indexes = get_entries_with_index_in(db, u, timeslot.id, required_indexes)
-		.await?
-		.into_iter()
-		.map(|v| v.index)
-		.collect::<Vec<_>>();
-
-	for i in found_indexes {
-		required_entries.remove(&(i.try_into().expect("Failed to convert u32 into usize")));
-	}
-
-	// All found entries have already been removed.
-	// Entry indices are always u32 or smaller.
-	let mut missing_entries = required_entries
-		.into_iter()
-		.map(|(i, d)| {
-			Ok(UnfilledEntry {
-				index: i.try_into()?,
-				timestamp: d.fixed_offset(),
-			})
-		})
-		.try_collect::<_, Vec<_>, std::num::TryFromIntError>()?;
+	Ok(missing.remove(&timeslot.id).unwrap_or_default())
+}
 
-	missing_entries.sort_unstable_by_key(|x| x.index);
+// Checks the required entries of all timeslots with a single query.
+// Every timeslot is contained in the result, even if it isn't missing any entries.
+pub async fn missing_entries_timeslots(
+	db: &PgPool,
+	u: &UserId,
+	timeslots: &[WebTimeSlot],
+) -> anyhow::Result<HashMap<Uuid, Vec<UnfilledEntry>>> {
+	let mut required_entries = HashMap::new();
+
+	for ts in timeslots {
+		// Entry indices are always u32 or smaller.
+		for (i, d) in get_entries(ts).enumerate() {
+			required_entries.insert((ts.id, i32::try_from(i)?), d);
+		}
+	}
 
-	Ok(missing_entries)
-}
+	debug!(
+		required = required_entries.len(),
+		"calculated required entries for timeslots"
+	);
 
-pub fn next_entry_date_timeslot(ts: &WebTimeSlot) -> Option<(u32, DateTime<chrono_tz::Tz>)> {
-	let start = ts.timerange.start.and_time(ts.time.start);
+	let (timeslot_ids, indexes): (Vec<_>, Vec<_>) = required_entries.keys().copied().unzip();
 
-	let start: DateTime<Tz> = ts.timezone.from_local_datetime(&start).single()?;
+	let mut missing: HashMap<_, _> = timeslots.iter().map(|ts| (ts.id, Vec::new())).collect();
 
-	let now = Utc::now();
+	for (timeslot_id, index) in get_missing_occurrences(db, u, &timeslot_ids, &indexes).await? {
+		let Some(timestamp) = required_entries.get(&(timeslot_id, index)) else {
+			continue;
+		};
 
-	let since = now - start.with_timezone(&Utc);
+		missing.entry(timeslot_id).or_default().push(UnfilledEntry {
+			index: index.try_into()?,
+			timestamp: timestamp.fixed_offset(),
+		});
+	}
 
-	// The first entry hasn't happened yet.
-	// So the first entry is the next entry.
-	if since < Duration::zero() {
-		return Some((0, start));
+	for entries in missing.values_mut() {
+		entries.sort_unstable_by_key(|x| x.index);
 	}
 
-	let seconds = since.num_seconds();
-	assert!(seconds >= 0);
+	Ok(missing)
+}
 
-	// Number of seconds from `start` until the next (from now) event
-	let next_seconds = crate::util::round_up_to_multiple(seconds, Duration::weeks(1).num_seconds());
+pub fn next_entry_date_timeslot(ts: &WebTimeSlot) -> Option<(u32, DateTime<chrono_tz::Tz>)> {
+	let now = Utc::now();
+
+	// Counting in local dates instead of seconds, so the local time stays the same across DST transitions.
+	let today = now.with_timezone(&ts.timezone).date_naive();
 
-	let next_date = start + Duration::seconds(next_seconds);
+	// The first entry hasn't happened yet, so the first entry is the next entry.
+	let days = (today - ts.timerange.start).num_days().max(0);
 
-	// Will never be negative
-	let raw_index = next_seconds / Duration::weeks(1).num_seconds();
-	assert!(raw_index >= 0);
+	let raw_index = crate::util::round_up_to_multiple(days, 7) / 7;
 
 	let index: u32 = raw_index
 		.try_into()
@@ -239,8 +235,17 @@ pub fn next_entry_date_timeslot(ts: &WebTimeSlot) -> Option<(u32, DateTime<chron
 		})
 		.ok()?;
 
-	// This is fine, since we check for a negative index.
-	Some((index, next_date))
+	// The occurrence today might already be over.
+	(index..=index.checked_add(1)?).find_map(|i| {
+		let date = ts
+			.timerange
+			.start
+			.checked_add_days(chrono::Days::new(u64::from(7 * i)))?;
+
+		let time = resolve_local_time(date.and_time(ts.time.start), ts.timezone)?;
+
+		(time >= now).then_some((i, time))
+	})
 }
 
 pub fn next_entry_timeslot(ts: &WebTimeSlot) -> anyhow::Result<UnfilledEntry> {
@@ -251,3 +256,109 @@ pub fn next_entry_timeslot(ts: &WebTimeSlot) -> anyhow::Result<UnfilledEntry> {
 		})
 		.context("timezone issue")
 }
+
+#[cfg(test)]
+mod test {
+	use chrono::{Datelike, NaiveDate, NaiveTime};
+	use chrono_tz::Tz;
+
+	use sqlx::PgPool;
+
+	use crate::auth::UserId;
+	use crate::db::model::WebTimeSlot;
+	use crate::db::queries::entry::delete_entry_by_id;
+	use crate::db::queries::entry::test::{insert_test_entry, insert_test_timeslot};
+	use crate::db::queries::timeslot::get_timeslot_by_id;
+
+	use super::{get_time_from_index_and_timeslot, missing_entries_timeslots};
+
+	// Weekly timeslot with an occurrence on `date`, the week before and the week after.
+	fn timeslot(date: NaiveDate, time: NaiveTime, timezone: Tz) -> WebTimeSlot {
+		let start = date - chrono::Duration::weeks(1);
+
+		WebTimeSlot {
+			time: time..time + chrono::Duration::hours(1),
+			timerange: start..date + chrono::Duration::weeks(1),
+			weekday: start.weekday(),
+			timezone,
+			..crate::db::model::test::timeslot()
+		}
+	}
+
+	fn occurrence(ts: &WebTimeSlot, index: u32) -> String {
+		get_time_from_index_and_timeslot(ts, index)
+			.unwrap()
+			.to_rfc3339()
+	}
+
+	#[test]
+	fn test_occurrences_across_dst() {
+		use chrono_tz::{America, Australia, Europe};
+
+		let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+		let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+		// Gaps
+		let ts = timeslot(date(2024, 3, 31), time(2, 30), Europe::Berlin);
+		assert_eq!(occurrence(&ts, 1), "2024-03-31T03:30:00+02:00");
+		let ts = timeslot(date(2024, 3, 10), time(2, 15), America::New_York);
+		assert_eq!(occurrence(&ts, 1), "2024-03-10T03:15:00-04:00");
+		let ts = timeslot(date(2024, 10, 6), time(2, 0), Australia::Sydney);
+		assert_eq!(occurrence(&ts, 1), "2024-10-06T03:00:00+11:00");
+
+		// Ambiguous times
+		let ts = timeslot(date(2024, 10, 27), time(2, 30), Europe::Berlin);
+		assert_eq!(occurrence(&ts, 1), "2024-10-27T02:30:00+02:00");
+		let ts = timeslot(date(2024, 11, 3), time(1, 0), America::New_York);
+		assert_eq!(occurrence(&ts, 1), "2024-11-03T01:00:00-04:00");
+		let ts = timeslot(date(2024, 4, 7), time(2, 0), Australia::Sydney);
+		assert_eq!(occurrence(&ts, 1), "2024-04-07T02:00:00+11:00");
+
+		// The weeks around the transition keep their local time
+		let ts = timeslot(date(2024, 3, 31), time(2, 30), Europe::Berlin);
+		assert_eq!(occurrence(&ts, 0), "2024-03-24T02:30:00+01:00");
+		assert_eq!(occurrence(&ts, 2), "2024-04-07T02:30:00+02:00");
+	}
+
+	#[sqlx::test]
+	async fn test_missing_entries_timeslots(db: PgPool) {
+		let u = UserId::from_db("hello".into());
+
+		// Mondays from 2024-01-01 to 2024-03-25, so indices 0 to 12.
+		let filled = insert_test_timeslot(&db, &u).await;
+		let empty = insert_test_timeslot(&db, &u).await;
+
+		for index in 0..=12 {
+			insert_test_entry(&db, &u, filled, index).await;
+		}
+
+		// Entries outside the timerange are neither required nor reported.
+		insert_test_entry(&db, &u, filled, 20).await;
+
+		// Deleted entries count as missing.
+		delete_entry_by_id(&db, &u, filled, 5)
+			.await
+			.unwrap()
+			.unwrap();
+
+		let timeslots = [
+			get_timeslot_by_id(&db, &u, filled).await.unwrap().unwrap(),
+			get_timeslot_by_id(&db, &u, empty).await.unwrap().unwrap(),
+		];
+
+		let missing = missing_entries_timeslots(&db, &u, &timeslots)
+			.await
+			.unwrap();
+
+		let indices = |id| missing[&id].iter().map(|e| e.index).collect::<Vec<_>>();
+
+		assert_eq!(indices(filled), vec![5]);
+		assert_eq!(indices(empty), (0..=12).collect::<Vec<_>>());
+		assert_eq!(
+			missing[&filled][0].timestamp,
+			get_time_from_index_and_timeslot(&timeslots[0], 5)
+				.unwrap()
+				.fixed_offset()
+		);
+	}
+}
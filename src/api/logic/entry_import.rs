@@ -0,0 +1,208 @@
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::api::logic::entry::get_index_from_date_and_timeslot;
+use crate::db::model::{EntryState, StudentState, StudentStatus, WebTimeSlot};
+
+#[derive(Deserialize)]
+pub struct CsvRow {
+	pub date: String,
+	pub subject: String,
+	pub state: String,
+	// Formatted as `name:status;name:status`
+	#[serde(default)]
+	pub students: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RowError {
+	#[error("invalid row: {0}")]
+	InvalidRow(String),
+	#[error("invalid date, expected YYYY-MM-DD")]
+	InvalidDate,
+	#[error("invalid state: {0}")]
+	InvalidState(String),
+	#[error("invalid student status: {0}")]
+	InvalidStudentStatus(String),
+	#[error("no timeslot with this subject takes place on this date")]
+	NoTimeslot,
+	#[error("multiple timeslots with this subject take place on this date")]
+	AmbiguousTimeslot,
+	#[error("students don't match the state or the timeslot")]
+	InvalidStudents,
+}
+
+pub fn parse_date(date: &str) -> Result<NaiveDate, RowError> {
+	NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| RowError::InvalidDate)
+}
+
+// Uses the same names as the database enum.
+pub fn parse_state(state: &str) -> Result<EntryState, RowError> {
+	use EntryState::*;
+	match state.to_ascii_lowercase().as_str() {
+		"success" => Ok(Success),
+		"cancelledbystudents" => Ok(CancelledByStudents),
+		"studentsmissing" => Ok(StudentsMissing),
+		"cancelledbytutor" => Ok(CancelledByTutor),
+		"holidays" => Ok(Holidays),
+		"other" => Ok(Other),
+		_ => Err(RowError::InvalidState(state.to_string())),
+	}
+}
+
+pub fn parse_students(students: &str) -> Result<Vec<StudentState>, RowError> {
+	students
+		.split(';')
+		.map(str::trim)
+		.filter(|s| !s.is_empty())
+		.map(|s| {
+			let Some((student, status)) = s.rsplit_once(':') else {
+				return Err(RowError::InvalidStudentStatus(s.to_string()));
+			};
+
+			let status = match status.trim().to_ascii_lowercase().as_str() {
+				"present" => StudentStatus::Present,
+				"pardoned" => StudentStatus::Pardoned,
+				"missing" => StudentStatus::Missing,
+				_ => return Err(RowError::InvalidStudentStatus(s.to_string())),
+			};
+
+			Ok(StudentState {
+				student: student.trim().to_string(),
+				status,
+			})
+		})
+		.collect()
+}
+
+// Returns the timeslot and the index of its occurrence on `date`.
+pub fn find_timeslot<'a>(
+	timeslots: &'a [WebTimeSlot],
+	subject: &str,
+	date: NaiveDate,
+) -> Result<(&'a WebTimeSlot, u32), RowError> {
+	let mut matching = timeslots
+		.iter()
+		.filter(|ts| ts.subject == subject)
+		.filter_map(|ts| get_index_from_date_and_timeslot(ts, date).map(|index| (ts, index)));
+
+	let found = matching.next().ok_or(RowError::NoTimeslot)?;
+
+	if matching.next().is_some() {
+		return Err(RowError::AmbiguousTimeslot);
+	}
+
+	Ok(found)
+}
+
+#[cfg(test)]
+mod test {
+	use chrono::NaiveTime;
+
+	use crate::api::logic::entry::get_index_from_date_and_timeslot;
+	use crate::db::model::test::{date, timeslot};
+	use crate::db::model::{EntryState, StudentStatus, WebTimeSlot};
+
+	use super::{find_timeslot, parse_state, parse_students, RowError};
+
+	#[test]
+	fn test_parse_state() {
+		assert_eq!(parse_state("Success").unwrap(), EntryState::Success);
+		assert_eq!(
+			parse_state("cancelledByTutor").unwrap(),
+			EntryState::CancelledByTutor
+		);
+		assert!(matches!(
+			parse_state("cancelled"),
+			Err(RowError::InvalidState(_))
+		));
+	}
+
+	#[test]
+	fn test_parse_students() {
+		let students = parse_students(" a: present;b:c:Missing ;").unwrap();
+
+		// Names may contain colons, the status is after the last one.
+		let students: Vec<_> = students
+			.iter()
+			.map(|s| (s.student.as_str(), s.status))
+			.collect();
+		assert_eq!(
+			students,
+			vec![
+				("a", StudentStatus::Present),
+				("b:c", StudentStatus::Missing)
+			]
+		);
+
+		assert!(parse_students("").unwrap().is_empty());
+		assert!(matches!(
+			parse_students("a"),
+			Err(RowError::InvalidStudentStatus(_))
+		));
+		assert!(matches!(
+			parse_students("a:late"),
+			Err(RowError::InvalidStudentStatus(_))
+		));
+	}
+
+	#[test]
+	fn test_get_index_from_date_and_timeslot() {
+		let ts = timeslot();
+
+		assert_eq!(
+			get_index_from_date_and_timeslot(&ts, date(2024, 1, 1)),
+			Some(0)
+		);
+		assert_eq!(
+			get_index_from_date_and_timeslot(&ts, date(2024, 3, 25)),
+			Some(12)
+		);
+		// Not a monday
+		assert_eq!(
+			get_index_from_date_and_timeslot(&ts, date(2024, 1, 2)),
+			None
+		);
+		// Outside of the timerange
+		assert_eq!(
+			get_index_from_date_and_timeslot(&ts, date(2023, 12, 25)),
+			None
+		);
+		assert_eq!(
+			get_index_from_date_and_timeslot(&ts, date(2024, 4, 1)),
+			None
+		);
+	}
+
+	#[test]
+	fn test_find_timeslot() {
+		let math = timeslot();
+		let later = WebTimeSlot {
+			time: NaiveTime::from_hms_opt(16, 0, 0).unwrap()
+				..NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+			timerange: date(2024, 3, 4)..date(2024, 6, 24),
+			..timeslot()
+		};
+		let timeslots = [math.clone(), later.clone()];
+
+		let (ts, index) = find_timeslot(&timeslots, "math", date(2024, 1, 8)).unwrap();
+		assert_eq!((ts.id, index), (math.id, 1));
+
+		let (ts, index) = find_timeslot(&timeslots, "math", date(2024, 4, 1)).unwrap();
+		assert_eq!((ts.id, index), (later.id, 4));
+
+		// Both timeslots take place on this date
+		assert!(matches!(
+			find_timeslot(&timeslots, "math", date(2024, 3, 4)),
+			Err(RowError::AmbiguousTimeslot)
+		));
+		assert!(matches!(
+			find_timeslot(&timeslots, "physics", date(2024, 1, 8)),
+			Err(RowError::NoTimeslot)
+		));
+		assert!(matches!(
+			find_timeslot(&timeslots, "math", date(2024, 1, 9)),
+			Err(RowError::NoTimeslot)
+		));
+	}
+}
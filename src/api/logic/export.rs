@@ -1,8 +1,411 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
 
+use anyhow::Context;
+use chrono::{Datelike, Duration, IsoWeek, NaiveDate, Weekday};
 use itertools::Itertools;
+use serde::Deserialize;
+use uuid::Uuid;
 
-use crate::db::model::{EntryState, StudentState, StudentStatus};
+use crate::api::logic::entry::get_time_from_index_and_timeslot;
+use crate::db::model::{EntryState, HasUserId, StudentState, StudentStatus, WebEntry, WebTimeSlot};
+use crate::util::{create_isoweek, isoweek_date_range};
+
+// German is what the reports were originally written in, so it stays the default.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+	#[default]
+	De,
+	En,
+	Fr,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+	#[default]
+	Week,
+	Month,
+}
+
+// Only a single variant is used in any given export, so the derived ordering is fine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExportGroup {
+	Week(IsoWeek),
+	Month { year: i32, month: u32 },
+}
+
+impl GroupBy {
+	pub fn group(self, date: NaiveDate) -> ExportGroup {
+		match self {
+			GroupBy::Week => ExportGroup::Week(date.iso_week()),
+			GroupBy::Month => ExportGroup::Month {
+				year: date.year(),
+				month: date.month(),
+			},
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExportTotals {
+	pub held: u32,
+	pub cancelled_by_students: u32,
+	pub cancelled_by_tutor: u32,
+	pub holidays: u32,
+	pub teaching_time: Duration,
+}
+
+impl Default for ExportTotals {
+	fn default() -> Self {
+		ExportTotals {
+			held: 0,
+			cancelled_by_students: 0,
+			cancelled_by_tutor: 0,
+			holidays: 0,
+			teaching_time: Duration::zero(),
+		}
+	}
+}
+
+impl ExportTotals {
+	// Only lessons which actually took place count towards the teaching time.
+	pub fn add_entry(&mut self, state: EntryState, lesson_length: Duration) {
+		match state {
+			EntryState::Success => {
+				self.held += 1;
+				self.teaching_time = self.teaching_time + lesson_length;
+			}
+			EntryState::CancelledByStudents => self.cancelled_by_students += 1,
+			EntryState::CancelledByTutor => self.cancelled_by_tutor += 1,
+			EntryState::Holidays => self.holidays += 1,
+			EntryState::StudentsMissing | EntryState::Other => (),
+		}
+	}
+
+	pub fn merge(&mut self, other: &ExportTotals) {
+		self.held += other.held;
+		self.cancelled_by_students += other.cancelled_by_students;
+		self.cancelled_by_tutor += other.cancelled_by_tutor;
+		self.holidays += other.holidays;
+		self.teaching_time = self.teaching_time + other.teaching_time;
+	}
+}
+
+pub fn lesson_length(ts: &WebTimeSlot) -> Duration {
+	ts.time.end - ts.time.start
+}
+
+// BtreeMap, because we need ordering
+// TODO: Vec<Student> should probably be Arc<[Student]> to save allocations.
+pub type ExportGroups = BTreeMap<ExportGroup, Vec<(WebEntry, Vec<String>)>>;
+
+// Adds the entries of `ts` to their groups and returns the totals of the timeslot.
+pub fn group_entries(
+	groups: &mut ExportGroups,
+	group_by: GroupBy,
+	ts: &WebTimeSlot,
+	entries: Vec<WebEntry>,
+) -> anyhow::Result<ExportTotals> {
+	let mut totals = ExportTotals::default();
+	let length = lesson_length(ts);
+
+	for e in entries {
+		totals.add_entry(e.state, length);
+
+		let group = group_by.group(
+			get_time_from_index_and_timeslot(ts, e.index)
+				.context(format!(
+					"unable to get time from from entry: {}",
+					e.identifier()
+				))?
+				.date_naive(),
+		);
+
+		groups
+			.entry(group)
+			.or_default()
+			.push((e, ts.students.clone()));
+	}
+
+	Ok(totals)
+}
+
+// One line per timeslot, followed by the totals of all timeslots.
+pub fn write_totals(
+	output: &mut impl fmt::Write,
+	locale: Locale,
+	timeslot_totals: &[(String, ExportTotals)],
+) -> fmt::Result {
+	let mut overall = ExportTotals::default();
+
+	writeln!(output)?;
+	writeln!(output, "{}", locale.totals_heading())?;
+	for (label, totals) in timeslot_totals {
+		overall.merge(totals);
+		writeln!(output, "{}", locale.format_totals(label, totals))?;
+	}
+	writeln!(
+		output,
+		"{}",
+		locale.format_totals(locale.overall_label(), &overall)
+	)
+}
+
+pub enum DateRangeError {
+	InvalidWeekYear,
+	InvalidDateRange,
+}
+
+// Dates take precedence over iso weeks, which are given as (year, week).
+pub fn date_range(
+	start_date: Option<NaiveDate>,
+	end_date: Option<NaiveDate>,
+	start_week: Option<(i32, u32)>,
+	end_week: Option<(i32, u32)>,
+) -> Result<Range<NaiveDate>, DateRangeError> {
+	let range = match (start_date, end_date) {
+		(Some(start), Some(end)) => start..end,
+		(None, None) => {
+			let (Some(start_week), Some(end_week)) = (start_week, end_week) else {
+				return Err(DateRangeError::InvalidDateRange);
+			};
+
+			let Some(start) = create_isoweek(start_week.0, start_week.1) else {
+				return Err(DateRangeError::InvalidWeekYear);
+			};
+
+			let Some(end) = create_isoweek(end_week.0, end_week.1) else {
+				return Err(DateRangeError::InvalidWeekYear);
+			};
+
+			isoweek_date_range(start..end).ok_or(DateRangeError::InvalidWeekYear)?
+		}
+		_ => return Err(DateRangeError::InvalidDateRange),
+	};
+
+	if range.start > range.end {
+		return Err(DateRangeError::InvalidDateRange);
+	}
+
+	Ok(range)
+}
+
+// Comma seperated, `None` if any of the ids is invalid.
+pub fn parse_timeslot_ids(ids: &str) -> Option<Vec<Uuid>> {
+	ids.split(',')
+		.map(|id| Uuid::parse_str(id.trim()).ok())
+		.collect()
+}
+
+// Selected ids, which don't belong to any of the timeslots.
+pub fn unknown_timeslot_ids(ids: &[Uuid], timeslots: &[WebTimeSlot]) -> Vec<Uuid> {
+	ids.iter()
+		.filter(|id| !timeslots.iter().any(|ts| ts.id == **id))
+		.copied()
+		.unique()
+		.collect()
+}
+
+// Filters which aren't given don't exclude any timeslots.
+pub fn includes_timeslot(ts: &WebTimeSlot, ids: Option<&[Uuid]>, subject: Option<&str>) -> bool {
+	ids.is_none_or(|ids| ids.contains(&ts.id)) && subject.is_none_or(|s| ts.subject == s)
+}
+
+// Timeslots are listed by weekday and start time.
+pub fn sort_timeslots(timeslots: &mut [WebTimeSlot]) {
+	timeslots.sort_by(|a, b| {
+		a.timerange
+			.start
+			.weekday()
+			.num_days_from_monday()
+			.cmp(&b.timerange.start.weekday().num_days_from_monday())
+			.then(a.time.start.cmp(&b.time.start))
+	});
+}
+
+const MONTHS_DE: [&str; 12] = [
+	"Januar",
+	"Februar",
+	"März",
+	"April",
+	"Mai",
+	"Juni",
+	"Juli",
+	"August",
+	"September",
+	"Oktober",
+	"November",
+	"Dezember",
+];
+const MONTHS_EN: [&str; 12] = [
+	"January",
+	"February",
+	"March",
+	"April",
+	"May",
+	"June",
+	"July",
+	"August",
+	"September",
+	"October",
+	"November",
+	"December",
+];
+const MONTHS_FR: [&str; 12] = [
+	"janvier",
+	"février",
+	"mars",
+	"avril",
+	"mai",
+	"juin",
+	"juillet",
+	"août",
+	"septembre",
+	"octobre",
+	"novembre",
+	"décembre",
+];
+
+const WEEKDAYS_DE: [&str; 7] = ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"];
+const WEEKDAYS_EN: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const WEEKDAYS_FR: [&str; 7] = ["lun", "mar", "mer", "jeu", "ven", "sam", "dim"];
+
+impl Locale {
+	pub fn totals_heading(self) -> &'static str {
+		match self {
+			Locale::De => "Summe",
+			Locale::En => "Totals",
+			Locale::Fr => "Totaux",
+		}
+	}
+
+	pub fn overall_label(self) -> &'static str {
+		match self {
+			Locale::De => "Gesamt",
+			Locale::En => "Overall",
+			Locale::Fr => "Total",
+		}
+	}
+
+	pub fn timeslot_label(self, ts: &WebTimeSlot) -> String {
+		format!(
+			"{} ({} {}-{})",
+			ts.subject,
+			self.weekday(ts.weekday),
+			ts.time.start.format("%H:%M"),
+			ts.time.end.format("%H:%M")
+		)
+	}
+
+	pub fn format_totals(self, label: &str, totals: &ExportTotals) -> String {
+		let ExportTotals {
+			held,
+			cancelled_by_students,
+			cancelled_by_tutor,
+			holidays,
+			teaching_time,
+		} = totals;
+
+		let hours = format!(
+			"{}:{:02}",
+			teaching_time.num_hours(),
+			teaching_time.num_minutes() % 60
+		);
+
+		match self {
+			Locale::De => format!("{label}: {held} stattgefunden, {cancelled_by_students} vom Matrosen abgesagt, {cancelled_by_tutor} von mir abgesagt, {holidays} Ferien, {hours} Stunden Unterricht"),
+			Locale::En => format!("{label}: {held} held, {cancelled_by_students} cancelled by students, {cancelled_by_tutor} cancelled by me, {holidays} holidays, {hours} hours taught"),
+			Locale::Fr => format!("{label}: {held} ayant eu lieu, {cancelled_by_students} annulé(s) par l'élève, {cancelled_by_tutor} annulé(s) par moi, {holidays} vacances, {hours} heures de cours"),
+		}
+	}
+
+	fn weekday(self, weekday: Weekday) -> &'static str {
+		let weekdays = match self {
+			Locale::De => WEEKDAYS_DE,
+			Locale::En => WEEKDAYS_EN,
+			Locale::Fr => WEEKDAYS_FR,
+		};
+
+		weekdays[weekday.num_days_from_monday() as usize]
+	}
+
+	pub fn group_heading(self, group: ExportGroup) -> String {
+		match group {
+			ExportGroup::Week(w) => self.week_heading(w.week()),
+			ExportGroup::Month { year, month } => self.month_heading(year, month),
+		}
+	}
+
+	fn month_heading(self, year: i32, month: u32) -> String {
+		let months = match self {
+			Locale::De => MONTHS_DE,
+			Locale::En => MONTHS_EN,
+			Locale::Fr => MONTHS_FR,
+		};
+
+		// chrono months are always 1..=12
+		let name = months[(month - 1) as usize];
+
+		format!("{name} {year}")
+	}
+
+	fn week_heading(self, week: u32) -> String {
+		match self {
+			Locale::De => format!("KW{week}"),
+			Locale::En => format!("CW{week}"),
+			Locale::Fr => format!("S{week}"),
+		}
+	}
+
+	fn base(self, state_enum: EntryState, students: &str) -> String {
+		use EntryState::*;
+		match self {
+			Locale::De => match state_enum {
+				Success => format!("Unterricht mit {students} hat planmäßig und erfolgreich stattgefunden."),
+				CancelledByStudents => format!("Unterricht mit {students} wurde vom Matrosen abgesagt und nicht nachgeholt."),
+				CancelledByTutor => format!("Unterricht mit {students} wurde von mir abgesagt und nicht nachgeholt."),
+				StudentsMissing => format!("Unterricht mit {students} konnte nicht stattfinden. Matrose(n) fehlte(n) unentschuldigt!"),
+				Holidays => "Ferien".to_string(),
+				Other => format!("Unterricht mit {students} konnte aus unbekannten Gründen nicht stattfinden."),
+			},
+			Locale::En => match state_enum {
+				Success => format!("Lesson with {students} took place as planned."),
+				CancelledByStudents => format!("Lesson with {students} was cancelled by the student(s) and not rescheduled."),
+				CancelledByTutor => format!("Lesson with {students} was cancelled by me and not rescheduled."),
+				StudentsMissing => format!("Lesson with {students} could not take place. Student(s) absent without excuse!"),
+				Holidays => "Holidays".to_string(),
+				Other => format!("Lesson with {students} could not take place for unknown reasons."),
+			},
+			Locale::Fr => match state_enum {
+				Success => format!("Le cours avec {students} a eu lieu comme prévu."),
+				CancelledByStudents => format!("Le cours avec {students} a été annulé par l'élève et n'a pas été rattrapé."),
+				CancelledByTutor => format!("Le cours avec {students} a été annulé par moi et n'a pas été rattrapé."),
+				StudentsMissing => format!("Le cours avec {students} n'a pas pu avoir lieu. Élève(s) absent(s) sans excuse !"),
+				Holidays => "Vacances".to_string(),
+				Other => format!("Le cours avec {students} n'a pas pu avoir lieu pour des raisons inconnues."),
+			},
+		}
+	}
+
+	fn pardoned(self, students: &str) -> String {
+		match self {
+			Locale::De => format!(" ({students} entschuldigt)"),
+			Locale::En => format!(" ({students} excused)"),
+			Locale::Fr => format!(" ({students} excusé(s))"),
+		}
+	}
+
+	fn missing(self, students: &str) -> String {
+		match self {
+			Locale::De => format!(" ({students} fehlte(n) unentschuldigt)"),
+			Locale::En => format!(" ({students} absent without excuse)"),
+			Locale::Fr => format!(" ({students} absent(s) sans excuse)"),
+		}
+	}
+}
 
 fn seperate_status_map(students: &[StudentState]) -> HashMap<StudentStatus, Vec<String>> {
 	// TODO increase if we add too many StudentStatuses
@@ -28,6 +431,7 @@ pub fn format_entry(
 	state_enum: EntryState,
 	students: &[StudentState],
 	timeslot_students: &[String],
+	locale: Locale,
 ) -> String {
 	let all_students = format_students(timeslot_students);
 
@@ -43,24 +447,25 @@ pub fn format_entry(
 		.get(&StudentStatus::Missing)
 		.map(|s| format_students(s));
 
-	let base =
-			match state_enum {
-				EntryState::Success => format!("Unterricht mit {} hat planmäßig und erfolgreich stattgefunden.", present_students.as_ref().unwrap_or(&all_students)),
-				EntryState::CancelledByStudents => format!("Unterricht mit {} wurde vom Matrosen abgesagt und nicht nachgeholt.", pardoned_students.as_ref().unwrap()),
-				EntryState::CancelledByTutor => format!("Unterricht mit {all_students} wurde von mir abgesagt und nicht nachgeholt."),
-				EntryState::StudentsMissing => format!("Unterricht mit {all_students} konnte nicht stattfinden. Matrose(n) fehlte(n) unentschuldigt!"),
-				EntryState::Holidays => "Ferien".to_string(),
-				EntryState::Other => format!("Unterricht mit {all_students} konnte aus unbekannten Gründen nicht stattfinden."),
-			};
+	let base = match state_enum {
+		EntryState::Success => locale.base(
+			state_enum,
+			present_students.as_ref().unwrap_or(&all_students),
+		),
+		EntryState::CancelledByStudents => {
+			locale.base(state_enum, pardoned_students.as_ref().unwrap())
+		}
+		_ => locale.base(state_enum, &all_students),
+	};
 
 	let pardoned = if let Some(students) = pardoned_students.as_ref() {
-		format!(" ({students} entschuldigt)")
+		locale.pardoned(students)
 	} else {
 		String::new()
 	};
 
 	let missing = if let Some(students) = missing_students {
-		format!(" ({students} fehlte(n) unentschuldigt)")
+		locale.missing(&students)
 	} else {
 		String::new()
 	};
@@ -71,3 +476,193 @@ pub fn format_entry(
 fn format_students(students: &[String]) -> String {
 	students.iter().join(", ")
 }
+
+#[cfg(test)]
+mod test {
+	use chrono::{NaiveTime, Weekday};
+	use uuid::Uuid;
+
+	use crate::db::model::test::{date, timeslot};
+	use crate::db::model::{EntryState, WebEntry, WebTimeSlot};
+	use crate::util::create_isoweek;
+
+	use super::{
+		date_range, group_entries, unknown_timeslot_ids, write_totals, DateRangeError, ExportGroup,
+		ExportGroups, GroupBy, Locale,
+	};
+
+	fn entry(ts: &WebTimeSlot, index: u32, state: EntryState) -> WebEntry {
+		WebEntry {
+			user_id: "hello".into(),
+			index,
+			timeslot_id: ts.id,
+			state,
+			students: Vec::new(),
+		}
+	}
+
+	fn group_indices(groups: &ExportGroups) -> Vec<(ExportGroup, Vec<u32>)> {
+		groups
+			.iter()
+			.map(|(group, entries)| (*group, entries.iter().map(|(e, _)| e.index).collect()))
+			.collect()
+	}
+
+	#[test]
+	fn test_date_range() {
+		assert_eq!(
+			date_range(Some(date(2024, 1, 1)), Some(date(2024, 2, 1)), None, None).ok(),
+			Some(date(2024, 1, 1)..date(2024, 2, 1))
+		);
+
+		// Weeks range from the monday of the first until the sunday of the last week.
+		assert_eq!(
+			date_range(None, None, Some((2024, 1)), Some((2024, 2))).ok(),
+			Some(date(2024, 1, 1)..date(2024, 1, 14))
+		);
+
+		// Dates take precedence over weeks.
+		assert_eq!(
+			date_range(
+				Some(date(2024, 1, 1)),
+				Some(date(2024, 2, 1)),
+				Some((2023, 1)),
+				Some((2023, 2))
+			)
+			.ok(),
+			Some(date(2024, 1, 1)..date(2024, 2, 1))
+		);
+
+		assert!(matches!(
+			date_range(Some(date(2024, 1, 1)), None, None, None),
+			Err(DateRangeError::InvalidDateRange)
+		));
+		assert!(matches!(
+			date_range(Some(date(2024, 2, 1)), Some(date(2024, 1, 1)), None, None),
+			Err(DateRangeError::InvalidDateRange)
+		));
+		assert!(matches!(
+			date_range(None, None, Some((2024, 1)), None),
+			Err(DateRangeError::InvalidDateRange)
+		));
+		assert!(matches!(
+			date_range(None, None, Some((2024, 54)), Some((2024, 55))),
+			Err(DateRangeError::InvalidWeekYear)
+		));
+	}
+
+	#[test]
+	fn test_group_entries() {
+		let ts = timeslot();
+
+		// 2024-01-01, 2024-01-08, 2024-01-29, 2024-02-05 and 2024-03-04
+		let entries = || {
+			[0, 1, 4, 5, 9]
+				.map(|i| entry(&ts, i, EntryState::Success))
+				.into()
+		};
+
+		let mut groups = ExportGroups::new();
+		group_entries(&mut groups, GroupBy::Month, &ts, entries()).unwrap();
+
+		let month = |year, month| ExportGroup::Month { year, month };
+		assert_eq!(
+			group_indices(&groups),
+			vec![
+				(month(2024, 1), vec![0, 1, 4]),
+				(month(2024, 2), vec![5]),
+				(month(2024, 3), vec![9]),
+			]
+		);
+
+		let mut groups = ExportGroups::new();
+		group_entries(&mut groups, GroupBy::Week, &ts, entries()).unwrap();
+
+		let week = |week| ExportGroup::Week(create_isoweek(2024, week).unwrap());
+		assert_eq!(
+			group_indices(&groups),
+			vec![
+				(week(1), vec![0]),
+				(week(2), vec![1]),
+				(week(5), vec![4]),
+				(week(6), vec![5]),
+				(week(10), vec![9]),
+			]
+		);
+
+		// Indices without an occurrence are an error.
+		let mut groups = ExportGroups::new();
+		assert!(group_entries(
+			&mut groups,
+			GroupBy::Week,
+			&ts,
+			vec![entry(&ts, 20, EntryState::Success)]
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn test_totals() {
+		use EntryState::*;
+
+		let math = timeslot();
+		// 2024-01-03 is a wednesday
+		let physics = WebTimeSlot {
+			id: Uuid::new_v4(),
+			subject: "physics".into(),
+			time: NaiveTime::from_hms_opt(10, 0, 0).unwrap()
+				..NaiveTime::from_hms_opt(11, 0, 0).unwrap(),
+			timerange: date(2024, 1, 3)..date(2024, 3, 27),
+			weekday: Weekday::Wed,
+			..timeslot()
+		};
+
+		let mut groups = ExportGroups::new();
+		let mut timeslot_totals = Vec::new();
+
+		let math_entries = vec![
+			entry(&math, 0, Success),
+			entry(&math, 1, Success),
+			entry(&math, 4, CancelledByStudents),
+			entry(&math, 5, Holidays),
+		];
+		let physics_entries = vec![
+			entry(&physics, 0, Success),
+			entry(&physics, 5, CancelledByTutor),
+			entry(&physics, 9, StudentsMissing),
+		];
+
+		for (ts, entries) in [(&math, math_entries), (&physics, physics_entries)] {
+			let totals = group_entries(&mut groups, GroupBy::Month, ts, entries).unwrap();
+
+			timeslot_totals.push((Locale::En.timeslot_label(ts), totals));
+		}
+
+		// Totals are per timeslot, even though the groups contain entries of both timeslots.
+		assert_eq!(groups.len(), 3);
+		assert_eq!(groups.values().map(Vec::len).sum::<usize>(), 7);
+
+		let mut output = String::new();
+		write_totals(&mut output, Locale::En, &timeslot_totals).unwrap();
+
+		assert_eq!(
+			output,
+			"\nTotals\n\
+			math (Mon 14:00-15:00): 2 held, 1 cancelled by students, 0 cancelled by me, 1 holidays, 2:00 hours taught\n\
+			physics (Wed 10:00-11:00): 1 held, 0 cancelled by students, 1 cancelled by me, 0 holidays, 1:00 hours taught\n\
+			Overall: 3 held, 1 cancelled by students, 1 cancelled by me, 1 holidays, 3:00 hours taught\n"
+		);
+	}
+
+	#[test]
+	fn test_unknown_timeslot_ids() {
+		let timeslots = [timeslot(), timeslot()];
+		let unknown = Uuid::new_v4();
+
+		assert!(unknown_timeslot_ids(&[timeslots[0].id, timeslots[1].id], &timeslots).is_empty());
+		assert_eq!(
+			unknown_timeslot_ids(&[timeslots[0].id, unknown, unknown], &timeslots),
+			vec![unknown]
+		);
+	}
+}
@@ -3,8 +3,12 @@ use tracing::error;
 use crate::auth::UserId;
 use crate::db::model::HasUserId;
 
+pub mod backup;
+pub mod calendar;
 pub mod entry;
+pub mod entry_import;
 pub mod export;
+pub mod timesheet;
 pub mod timeslot;
 
 pub fn check_object_belong_to_userid<'a, T: HasUserId + 'a>(
@@ -0,0 +1,195 @@
+use anyhow::Context;
+
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::api::logic::entry::get_time_from_index_and_timeslot;
+use crate::api::logic::export::lesson_length;
+use crate::db::model::{EntryState, HasUserId, WebEntry, WebTimeSlot};
+
+// Lessons with `EntryState::Success` are always billable.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BillingRules {
+	pub cancelled_by_students: bool,
+	pub students_missing: bool,
+}
+
+impl BillingRules {
+	pub fn is_billable(self, state: EntryState) -> bool {
+		match state {
+			EntryState::Success => true,
+			EntryState::CancelledByStudents => self.cancelled_by_students,
+			EntryState::StudentsMissing => self.students_missing,
+			EntryState::CancelledByTutor | EntryState::Holidays | EntryState::Other => false,
+		}
+	}
+}
+
+// Rounded to the nearest cent per lesson, so the items of an invoice add up to its total.
+fn lesson_amount(minutes: i64, hourly_rate: u32) -> i64 {
+	(minutes * i64::from(hourly_rate) + 30) / 60
+}
+
+#[derive(Serialize)]
+pub struct TimesheetItem {
+	pub timeslot_id: Uuid,
+	pub subject: String,
+	pub index: u32,
+	pub timestamp: DateTime<FixedOffset>,
+	pub state: EntryState,
+	pub minutes: i64,
+	// In cents, `None` if the timeslot doesn't have an hourly rate.
+	pub amount: Option<i64>,
+}
+
+#[derive(Serialize, Default)]
+pub struct Timesheet {
+	pub items: Vec<TimesheetItem>,
+	pub total_minutes: i64,
+	// Only includes items, which have an amount.
+	pub total_amount: i64,
+}
+
+impl Timesheet {
+	pub fn add_timeslot(
+		&mut self,
+		ts: &WebTimeSlot,
+		entries: Vec<WebEntry>,
+		rules: BillingRules,
+	) -> anyhow::Result<()> {
+		let minutes = lesson_length(ts).num_minutes();
+		let amount = ts.hourly_rate.map(|rate| lesson_amount(minutes, rate));
+
+		for e in entries {
+			if !rules.is_billable(e.state) {
+				continue;
+			}
+
+			let timestamp = get_time_from_index_and_timeslot(ts, e.index)
+				.context(format!(
+					"unable to get time from from entry: {}",
+					e.identifier()
+				))?
+				.fixed_offset();
+
+			self.total_minutes += minutes;
+			self.total_amount += amount.unwrap_or(0);
+
+			self.items.push(TimesheetItem {
+				timeslot_id: ts.id,
+				subject: ts.subject.clone(),
+				index: e.index,
+				timestamp,
+				state: e.state,
+				minutes,
+				amount,
+			});
+		}
+
+		Ok(())
+	}
+
+	pub fn sort(&mut self) {
+		self.items.sort_unstable_by_key(|i| i.timestamp);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use chrono::NaiveTime;
+
+	use crate::api::logic::timeslot::get_index_range_timeslot;
+	use crate::db::model::test::{date, timeslot};
+	use crate::db::model::{EntryState, WebEntry, WebTimeSlot};
+
+	use super::{BillingRules, Timesheet};
+
+	fn entries(ts: &WebTimeSlot, states: &[(u32, EntryState)]) -> Vec<WebEntry> {
+		states
+			.iter()
+			.map(|&(index, state)| WebEntry {
+				user_id: "hello".into(),
+				index,
+				timeslot_id: ts.id,
+				state,
+				students: Vec::new(),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_is_billable() {
+		let rules = BillingRules::default();
+		assert!(rules.is_billable(EntryState::Success));
+		assert!(!rules.is_billable(EntryState::CancelledByStudents));
+		assert!(!rules.is_billable(EntryState::StudentsMissing));
+
+		let rules = BillingRules {
+			cancelled_by_students: true,
+			students_missing: true,
+		};
+		assert!(rules.is_billable(EntryState::CancelledByStudents));
+		assert!(rules.is_billable(EntryState::StudentsMissing));
+		assert!(!rules.is_billable(EntryState::CancelledByTutor));
+		assert!(!rules.is_billable(EntryState::Holidays));
+		assert!(!rules.is_billable(EntryState::Other));
+	}
+
+	#[test]
+	fn test_timesheet() {
+		// 45 minutes at 10.01 per hour are 7.5075
+		let rated = WebTimeSlot {
+			time: NaiveTime::from_hms_opt(14, 0, 0).unwrap()
+				..NaiveTime::from_hms_opt(14, 45, 0).unwrap(),
+			hourly_rate: Some(1001),
+			..timeslot()
+		};
+		let unrated = timeslot();
+
+		let mut timesheet = Timesheet::default();
+		timesheet
+			.add_timeslot(
+				&rated,
+				entries(
+					&rated,
+					&[
+						(0, EntryState::Success),
+						(1, EntryState::Success),
+						(2, EntryState::Holidays),
+					],
+				),
+				BillingRules::default(),
+			)
+			.unwrap();
+		timesheet
+			.add_timeslot(
+				&unrated,
+				entries(&unrated, &[(0, EntryState::Success)]),
+				BillingRules::default(),
+			)
+			.unwrap();
+
+		let amounts: Vec<_> = timesheet.items.iter().map(|i| i.amount).collect();
+		assert_eq!(amounts, vec![Some(751), Some(751), None]);
+
+		// Lessons without an hourly rate only count towards the minutes.
+		assert_eq!(timesheet.total_minutes, 45 + 45 + 60);
+		assert_eq!(timesheet.total_amount, 751 + 751);
+	}
+
+	#[test]
+	fn test_timesheet_range() {
+		let ts = timeslot();
+
+		// Lessons on the first and last day of the range are included.
+		assert_eq!(
+			get_index_range_timeslot(&ts, date(2024, 1, 8)..date(2024, 1, 22)),
+			Some(1..3)
+		);
+		assert_eq!(
+			get_index_range_timeslot(&ts, date(2024, 1, 8)..date(2024, 1, 8)),
+			Some(1..1)
+		);
+	}
+}
@@ -1,14 +1,120 @@
 use std::ops::Range;
 
-use chrono::{IsoWeek, NaiveDate, Weekday};
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
 use tracing::trace;
+use uuid::Uuid;
 
-use crate::db::model::WebTimeSlot;
+use crate::api::logic::entry::get_time_from_index_and_timeslot;
+use crate::auth::UserId;
+use crate::db::model::{convert_ts, DbTime, DbTimerange, Student, TimeSlot, WebTimeSlot};
 
-// Returns all timeslots indices, which fall into timerange
-pub fn get_index_range_timeslot(ts: &WebTimeSlot, range: Range<IsoWeek>) -> Option<Range<u32>> {
-	let start = NaiveDate::from_isoywd_opt(range.start.year(), range.start.week(), Weekday::Sun)?;
-	let end = NaiveDate::from_isoywd_opt(range.end.year(), range.end.week(), Weekday::Sun)?;
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimeslotCreate {
+	pub students: Vec<Student>,
+	pub subject: String,
+	pub weekday: Weekday,
+	pub time: Range<NaiveTime>,
+	pub timerange: Range<NaiveDate>,
+	pub timezone: Tz,
+	#[serde(default)]
+	pub hourly_rate: Option<u32>,
+}
+
+impl TimeslotCreate {
+	pub fn validate(&self) -> Result<(), TimeslotCreateError> {
+		if self.timerange.start.weekday() != self.weekday {
+			return Err(TimeslotCreateError::TimerangeStartShouldBeWeekday);
+		}
+
+		if self.timerange.start > self.timerange.end {
+			return Err(TimeslotCreateError::TimerangeStartShouldBeBeforeEnd);
+		}
+
+		if self.time.start > self.time.end {
+			return Err(TimeslotCreateError::StartTimeShouldBeBeforeEndTime);
+		}
+
+		Ok(())
+	}
+
+	// Copies everything except the timerange.
+	pub fn from_timeslot(ts: &WebTimeSlot, timerange: Range<NaiveDate>) -> TimeslotCreate {
+		TimeslotCreate {
+			students: ts
+				.students
+				.iter()
+				.map(|name| Student { name: name.clone() })
+				.collect(),
+			subject: ts.subject.clone(),
+			weekday: ts.weekday,
+			time: ts.time.clone(),
+			timerange,
+			timezone: ts.timezone,
+			hourly_rate: ts.hourly_rate,
+		}
+	}
+
+	// Only used to compare the timeslot with existing ones, it doesn't have an id yet.
+	pub fn to_web(&self, u: &UserId) -> anyhow::Result<WebTimeSlot> {
+		convert_ts(self.clone().into_timeslot(u, Uuid::nil())?).context("invalid timeslot")
+	}
+
+	// Doesn't validate, call `validate` first.
+	pub fn into_timeslot(
+		self,
+		u: &UserId,
+		id: Uuid,
+	) -> Result<TimeSlot, std::num::TryFromIntError> {
+		Ok(TimeSlot {
+			user_id: u.as_str().to_owned(),
+			id,
+			subject: self.subject,
+			students: self
+				.students
+				.into_iter()
+				.map(|student| student.name)
+				.collect(),
+			time: DbTime {
+				beginning: self.time.start,
+				finish: self.time.end,
+			},
+			timerange: DbTimerange {
+				beginning: self.timerange.start,
+				finish: self.timerange.end,
+			},
+			timezone: self.timezone.name().to_string(),
+			hourly_rate: self.hourly_rate.map(i32::try_from).transpose()?,
+			archived: None,
+		})
+	}
+}
+
+pub enum TimeslotCreateError {
+	TimerangeStartShouldBeWeekday,
+	TimerangeStartShouldBeBeforeEnd,
+	StartTimeShouldBeBeforeEndTime,
+}
+
+impl TimeslotCreateError {
+	pub fn message(&self) -> &'static str {
+		use TimeslotCreateError::*;
+		match self {
+			TimerangeStartShouldBeWeekday => {
+				"weekday of timerange.start should be equal to weekday"
+			}
+			TimerangeStartShouldBeBeforeEnd => "timerange.start should be before timerange.end",
+			StartTimeShouldBeBeforeEndTime => "time.start should be before time.end",
+		}
+	}
+}
+
+// Returns all timeslots indices, which fall into timerange.
+// Both `range.end` and the end of the returned range are inclusive.
+pub fn get_index_range_timeslot(ts: &WebTimeSlot, range: Range<NaiveDate>) -> Option<Range<u32>> {
+	let Range { start, end } = range;
 
 	trace!(%start, %end, ts_id=%ts.id, "Getting timeslots in range.");
 
@@ -22,7 +128,11 @@ pub fn get_index_range_timeslot(ts: &WebTimeSlot, range: Range<IsoWeek>) -> Opti
 		if start < ts.timerange.start {
 			0
 		} else {
-			(start - ts.timerange.start).num_weeks().try_into().ok()?
+			// Round up, since the occurrence before `start` isn't part of the range.
+			let days = (start - ts.timerange.start).num_days();
+			(crate::util::round_up_to_multiple(days, 7) / 7)
+				.try_into()
+				.ok()?
 		}
 	};
 	trace!(start_index, "got start index");
@@ -38,7 +148,185 @@ pub fn get_index_range_timeslot(ts: &WebTimeSlot, range: Range<IsoWeek>) -> Opti
 	};
 	trace!(end_index, "got end index");
 
-	assert!(start_index <= end_index);
+	// The range might not contain the timeslots weekday at all.
+	if start_index > end_index {
+		return None;
+	}
 
 	Some(start_index..end_index)
 }
+
+// First date on or after `date`, which falls on `weekday`.
+pub fn next_weekday(date: NaiveDate, weekday: Weekday) -> Option<NaiveDate> {
+	let days = (7 + weekday.num_days_from_monday() - date.weekday().num_days_from_monday()) % 7;
+
+	date.checked_add_days(Days::new(days.into()))
+}
+
+// All occurrences in `range` as absolute times.
+fn occurrences(
+	ts: &WebTimeSlot,
+	range: Range<NaiveDate>,
+) -> impl Iterator<Item = Range<DateTime<Utc>>> + '_ {
+	let length = ts.time.end - ts.time.start;
+
+	get_index_range_timeslot(ts, range)
+		.into_iter()
+		.flat_map(|r| r.start..=r.end)
+		.filter_map(move |i| {
+			let start = get_time_from_index_and_timeslot(ts, i)?.with_timezone(&Utc);
+
+			Some(start..start + length)
+		})
+}
+
+// Occurrences are compared as absolute times, so timeslots in different timezones are handled correctly.
+pub fn timeslots_overlap(a: &WebTimeSlot, b: &WebTimeSlot) -> bool {
+	// Converting between timezones shifts dates by at most a day.
+	let (Some(start), Some(end)) = (
+		a.timerange.start.max(b.timerange.start).pred_opt(),
+		a.timerange.end.min(b.timerange.end).succ_opt(),
+	) else {
+		return false;
+	};
+
+	if start > end {
+		return false;
+	}
+
+	let b_occurrences: Vec<_> = occurrences(b, start..end).collect();
+
+	occurrences(a, start..end).any(|x| {
+		b_occurrences
+			.iter()
+			.any(|y| x.start < y.end && y.start < x.end)
+	})
+}
+
+// Timeslots which overlap another timeslot of the same batch.
+pub fn overlapping_within(timeslots: &[WebTimeSlot]) -> Vec<&WebTimeSlot> {
+	timeslots
+		.iter()
+		.enumerate()
+		.filter(|(i, ts)| {
+			timeslots
+				.iter()
+				.enumerate()
+				.any(|(j, other)| *i != j && timeslots_overlap(ts, other))
+		})
+		.map(|(_, ts)| ts)
+		.collect()
+}
+
+#[cfg(test)]
+mod test {
+	use chrono::{NaiveTime, Weekday};
+
+	use crate::db::model::test::{date, timeslot};
+
+	use super::{get_index_range_timeslot, next_weekday, overlapping_within, timeslots_overlap};
+
+	#[test]
+	fn test_get_index_range_timeslot() {
+		let ts = timeslot();
+
+		// Whole month of january
+		assert_eq!(
+			get_index_range_timeslot(&ts, date(2024, 1, 1)..date(2024, 1, 31)),
+			Some(0..4)
+		);
+		// Starting on a tuesday skips that weeks monday
+		assert_eq!(
+			get_index_range_timeslot(&ts, date(2024, 1, 2)..date(2024, 1, 31)),
+			Some(1..4)
+		);
+		// Clamped to the end of the timeslot
+		assert_eq!(
+			get_index_range_timeslot(&ts, date(2024, 3, 1)..date(2024, 12, 31)),
+			Some(9..12)
+		);
+		// No monday in range
+		assert_eq!(
+			get_index_range_timeslot(&ts, date(2024, 1, 2)..date(2024, 1, 7)),
+			None
+		);
+		// Outside of timeslot
+		assert_eq!(
+			get_index_range_timeslot(&ts, date(2023, 1, 1)..date(2023, 12, 31)),
+			None
+		);
+	}
+
+	#[test]
+	fn test_next_weekday() {
+		// 2024-01-03 is a wednesday
+		assert_eq!(
+			next_weekday(date(2024, 1, 3), Weekday::Wed),
+			Some(date(2024, 1, 3))
+		);
+		assert_eq!(
+			next_weekday(date(2024, 1, 3), Weekday::Fri),
+			Some(date(2024, 1, 5))
+		);
+		assert_eq!(
+			next_weekday(date(2024, 1, 3), Weekday::Mon),
+			Some(date(2024, 1, 8))
+		);
+		assert_eq!(
+			next_weekday(date(2024, 1, 3), Weekday::Tue),
+			Some(date(2024, 1, 9))
+		);
+	}
+
+	#[test]
+	fn test_timeslots_overlap() {
+		let ts = timeslot();
+
+		// 13:30 - 14:30 in London is 14:30 - 15:30 in Berlin
+		let mut other = timeslot();
+		other.time = NaiveTime::from_hms_opt(13, 30, 0).unwrap()
+			..NaiveTime::from_hms_opt(14, 30, 0).unwrap();
+		other.timezone = chrono_tz::Europe::London;
+		assert!(timeslots_overlap(&ts, &other));
+		assert!(timeslots_overlap(&other, &ts));
+
+		// Directly after the other timeslot
+		other.time =
+			NaiveTime::from_hms_opt(14, 0, 0).unwrap()..NaiveTime::from_hms_opt(15, 0, 0).unwrap();
+		assert!(!timeslots_overlap(&ts, &other));
+
+		// Same time on another weekday
+		let mut other = timeslot();
+		other.timerange = date(2024, 1, 2)..date(2024, 3, 26);
+		other.weekday = Weekday::Tue;
+		assert!(!timeslots_overlap(&ts, &other));
+
+		// Timeranges only share the last monday
+		let mut other = timeslot();
+		other.timerange = date(2024, 3, 25)..date(2024, 6, 24);
+		assert!(timeslots_overlap(&ts, &other));
+
+		// Timeranges don't intersect
+		other.timerange = date(2024, 4, 1)..date(2024, 6, 24);
+		assert!(!timeslots_overlap(&ts, &other));
+	}
+
+	#[test]
+	fn test_overlapping_within() {
+		let ts = timeslot();
+
+		let mut later = timeslot();
+		later.time =
+			NaiveTime::from_hms_opt(16, 0, 0).unwrap()..NaiveTime::from_hms_opt(17, 0, 0).unwrap();
+
+		assert!(overlapping_within(&[ts.clone(), later.clone()]).is_empty());
+
+		// Only the timeslots overlapping each other are returned.
+		let same = timeslot();
+		let overlapping: Vec<_> = overlapping_within(&[ts.clone(), later, same.clone()])
+			.into_iter()
+			.map(|ts| ts.id)
+			.collect();
+		assert_eq!(overlapping, vec![ts.id, same.id]);
+	}
+}
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use axum::body::Body;
 use axum::http::Request;
-use axum::routing::{delete, get};
+use axum::routing::{delete, get, post, put};
 use axum::Router;
 
 use sqlx::PgPool;
@@ -13,13 +13,21 @@ use tracing::{info, info_span};
 use crate::auth::Authenticator;
 use crate::configuration::Config;
 
+mod account;
+mod agenda;
+mod audit;
 mod auth;
+mod calendar;
 mod entry;
+mod feed_token;
 mod health;
-mod logic;
+pub(crate) mod logic;
+mod timesheet;
 mod timeslot;
+mod trash;
 #[macro_use]
 mod util;
+mod week;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -28,6 +36,17 @@ pub struct AppState {
 	pub cfg: Arc<Config>,
 }
 
+// These can also be accessed using a feed token, since calendar apps can't use the oidc flow.
+fn feed_routes(state: &AppState) -> Router<AppState> {
+	Router::new()
+		.route("/timeslots/export", get(timeslot::export))
+		.route("/calendar.ics", get(calendar::ics))
+		.layer(axum::middleware::from_fn_with_state(
+			state.clone(),
+			auth::feed_auth_middleware,
+		))
+}
+
 pub async fn run(db: PgPool, cfg: Config, auth: Authenticator) {
 	let hosturl = cfg.hosturl;
 
@@ -41,8 +60,16 @@ pub async fn run(db: PgPool, cfg: Config, auth: Authenticator) {
 
 	let app = Router::new()
 		.route("/timeslots", get(timeslot::query).post(timeslot::create))
-		.route("/timeslots/export", get(timeslot::export))
+		.route("/timeslots/import", post(timeslot::import))
+		.route("/timeslots/clone", post(timeslot::clone_many))
 		.route("/timeslots/:id", delete(timeslot::delete))
+		.route("/timeslots/:id/restore", post(timeslot::restore))
+		.route("/timeslots/:id/clone", post(timeslot::clone))
+		.route(
+			"/timeslots/:id/archive",
+			put(timeslot::archive).delete(timeslot::unarchive),
+		)
+		.route("/timeslots/:id/hourly_rate", put(timeslot::set_hourly_rate))
 		.route(
 			"/timeslots/:id/entries",
 			get(entry::query).post(entry::create),
@@ -50,12 +77,35 @@ pub async fn run(db: PgPool, cfg: Config, auth: Authenticator) {
 		.route("/timeslots/:id/entries/next", get(entry::next))
 		.route("/timeslots/:id/entries/missing", get(entry::missing))
 		.route("/timeslots/:id/entries/:index", delete(entry::delete))
+		.route("/timeslots/:id/entries/:index/restore", post(entry::restore))
 		.route("/timeslots/information", get(timeslot::information))
+		.route("/entries", get(entry::query_range))
+		.route("/entries/import", post(entry::import))
+		.route("/entries/missing", get(entry::missing_all))
+		.route("/timesheets", get(timesheet::query))
+		.route("/trash", get(trash::query))
+		.route("/week", get(week::query))
+		.route("/agenda", get(agenda::query))
+		.route(
+			"/feed_token",
+			post(feed_token::create)
+				.put(feed_token::rotate)
+				.delete(feed_token::revoke),
+		)
+		.route("/account", delete(account::delete))
+		.route(
+			"/account/deletion",
+			get(account::deletion_status).delete(account::cancel_deletion),
+		)
+		.route("/account/export", get(account::export))
+		.route("/account/import", post(account::import))
+		.route("/audit", get(audit::query))
 		.route("/auth/user_id", get(auth::user_id))
 		.layer(axum::middleware::from_fn_with_state(
 			state.clone(),
 			auth::auth_middleware,
 		))
+		.merge(feed_routes(&state))
 		.route("/auth/oidc_callback", get(auth::authenticate))
 		.route("/auth/oidc_login", get(auth::sign_in))
 		.route("/health_check", get(health::health_check))
@@ -66,7 +116,8 @@ pub async fn run(db: PgPool, cfg: Config, auth: Authenticator) {
 					.get::<RequestId>()
 					.map_or_else(|| "unknown".into(), ToString::to_string);
 
-				let span = info_span!("request", id = %request_id, method = %request.method(), uri = %request.uri());
+				// Query strings aren't logged, since they can contain feed tokens and student names.
+				let span = info_span!("request", id = %request_id, method = %request.method(), path = %request.uri().path());
 
                 {
                     let _enter = span.enter();
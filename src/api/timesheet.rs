@@ -0,0 +1,87 @@
+use std::ops::Range;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Extension;
+
+use chrono::NaiveDate;
+
+use serde::Deserialize;
+
+use tracing::warn;
+
+use crate::api::logic::check_object_belong_to_userid;
+use crate::api::logic::timesheet::{BillingRules, Timesheet};
+use crate::api::logic::timeslot::get_index_range_timeslot;
+use crate::api::util::prelude::*;
+use crate::api::AppState;
+use crate::auth::UserId;
+use crate::db::queries::entry::get_entry_by_index_range;
+use crate::db::queries::timeslot::get_timeslots;
+
+#[derive(Deserialize)]
+pub struct TimesheetRequest {
+	start_date: NaiveDate,
+	end_date: NaiveDate,
+	#[serde(default)]
+	bill_cancelled_by_students: bool,
+	#[serde(default)]
+	bill_students_missing: bool,
+}
+
+pub enum TimesheetError {
+	InvalidDateRange,
+}
+
+impl From<TimesheetError> for WebError<&'static str> {
+	fn from(v: TimesheetError) -> WebError<&'static str> {
+		use TimesheetError::*;
+		match v {
+			InvalidDateRange => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				"start_date should be before end_date",
+			)
+				.into(),
+		}
+	}
+}
+
+pub async fn query(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Query(q): Query<TimesheetRequest>,
+) -> WebResult<Timesheet, &'static str> {
+	if q.start_date > q.end_date {
+		return Err(TimesheetError::InvalidDateRange)?;
+	}
+
+	let rules = BillingRules {
+		cancelled_by_students: q.bill_cancelled_by_students,
+		students_missing: q.bill_students_missing,
+	};
+
+	let timeslots = get_timeslots(&db, &u, true).await?;
+
+	check_object_belong_to_userid(timeslots.iter(), &u)?;
+
+	let mut timesheet = Timesheet::default();
+
+	for ts in timeslots {
+		let Some(r) = get_index_range_timeslot(&ts, q.start_date..q.end_date) else {
+			warn!(ts=%ts.id, start=%q.start_date, end=%q.end_date, "timerange invalid for timeslot");
+			continue;
+		};
+
+		let r: Range<i32> = r.start.try_into()?..r.end.try_into()?;
+
+		let entries = get_entry_by_index_range(&db, &u, ts.id, r).await?;
+
+		check_object_belong_to_userid(entries.iter(), &u)?;
+
+		timesheet.add_timeslot(&ts, entries, rules)?;
+	}
+
+	timesheet.sort();
+
+	Ok(timesheet.into())
+}
@@ -1,4 +1,3 @@
-use std::collections::BTreeMap;
 use std::fmt::Write;
 use std::ops::Range;
 
@@ -8,8 +7,7 @@ use axum::extract::{Json, Path, Query, State};
 use axum::http::StatusCode;
 use axum::Extension;
 
-use chrono::{Datelike, IsoWeek, NaiveDate, NaiveTime, Weekday};
-use chrono_tz::Tz;
+use chrono::NaiveDate;
 
 use futures_util::{
 	stream::{FuturesOrdered, StreamExt},
@@ -20,32 +18,42 @@ use itertools::Itertools;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+use tower_request_id::RequestId;
 use tracing::{debug, warn};
 use uuid::Uuid;
 
 use crate::api::entry::UnfilledEntry;
+use crate::api::logic::calendar::parse_calendar;
 use crate::api::logic::check_object_belong_to_userid;
-use crate::api::logic::entry::{
-	get_time_from_index_and_timeslot, missing_entries, next_entry_timeslot,
+use crate::api::logic::entry::{missing_entries_timeslots, next_entry_timeslot};
+use crate::api::logic::export::{
+	date_range, format_entry, group_entries, includes_timeslot, parse_timeslot_ids, sort_timeslots,
+	unknown_timeslot_ids, write_totals, DateRangeError, ExportGroups, ExportTotals, GroupBy,
+	Locale,
+};
+use crate::api::logic::timeslot::{
+	get_index_range_timeslot, next_weekday, overlapping_within, timeslots_overlap, TimeslotCreate,
+	TimeslotCreateError,
 };
-use crate::api::logic::export::format_entry;
-use crate::api::logic::timeslot::get_index_range_timeslot;
 use crate::api::util::{prelude::*, WebError};
 use crate::auth::UserId;
 
-use crate::db::model::{DbTime, DbTimerange, HasUserId, Student, TimeSlot, WebEntry, WebTimeSlot};
-use crate::db::queries::entry::get_entry_by_index_range;
+use crate::db::model::{convert_ts, WebTimeSlot};
+use crate::db::queries::audit::{log_entry, log_timeslot, AuditAction, AuditContext};
+use crate::db::queries::entry::{get_entries_by_timeslot_id, get_entry_by_index_range};
 use crate::db::queries::timeslot::{
 	delete_timeslot_by_id, get_timeslot_by_id, get_timeslots, insert_timeslot,
+	restore_timeslot_by_id, set_timeslot_archived, update_timeslot_hourly_rate,
 };
 
-use crate::util::create_isoweek;
-
 use super::AppState;
 
 #[derive(Deserialize, Debug)]
 pub struct TimeSlotQuery {
 	id: Option<Uuid>,
+	#[serde(default)]
+	include_archived: bool,
 }
 
 pub async fn query(
@@ -63,7 +71,7 @@ pub async fn query(
 
 			output
 		}
-		None => get_timeslots(&db, &u).await?,
+		None => get_timeslots(&db, &u, q.include_archived).await?,
 	};
 
 	check_object_belong_to_userid(res.iter(), &u)?;
@@ -71,46 +79,57 @@ pub async fn query(
 	Ok(res.into())
 }
 
-#[derive(Deserialize, Debug)]
-pub struct TimeslotCreate {
-	students: Vec<Student>,
-	subject: String,
-	weekday: Weekday,
-	time: Range<NaiveTime>,
-	timerange: Range<NaiveDate>,
-	timezone: Tz,
+#[allow(clippy::from_over_into)]
+impl<E: From<&'static str> + Serialize> From<TimeslotCreateError> for WebError<E> {
+	fn from(v: TimeslotCreateError) -> WebError<E> {
+		(StatusCode::UNPROCESSABLE_ENTITY, v.message().into()).into()
+	}
+}
+
+#[derive(Deserialize)]
+pub struct OverlapQuery {
+	#[serde(default)]
+	pub allow_overlap: bool,
 }
 
-pub enum TimeslotCreateError {
-	TimerangeStartShouldBeWeekday,
-	TimerangeStartShouldBeBeforeEnd,
-	StartTimeShouldBeBeforeEndTime,
+pub enum OverlapError {
+	Overlapping(Vec<WebTimeSlot>),
 }
 
-#[allow(clippy::from_over_into)]
-impl From<TimeslotCreateError> for WebError<&'static str> {
-	fn from(v: TimeslotCreateError) -> WebError<&'static str> {
-		use TimeslotCreateError::*;
+impl From<OverlapError> for WebError<Value> {
+	fn from(v: OverlapError) -> WebError<Value> {
 		match v {
-			TimerangeStartShouldBeWeekday => (
-				StatusCode::UNPROCESSABLE_ENTITY,
-				"weekday of timerange.start should be equal to weekday",
-			)
-				.into(),
-			TimerangeStartShouldBeBeforeEnd => (
-				StatusCode::UNPROCESSABLE_ENTITY,
-				"timerange.start should be before timerange.end",
-			)
-				.into(),
-			StartTimeShouldBeBeforeEndTime => (
-				StatusCode::UNPROCESSABLE_ENTITY,
-				"time.start should be before time.end",
+			OverlapError::Overlapping(timeslots) => (
+				StatusCode::CONFLICT,
+				serde_json::json!({ "overlapping_timeslots": timeslots }),
 			)
 				.into(),
 		}
 	}
 }
 
+// Checks the new timeslots against all existing timeslots of the user and against each other.
+pub async fn check_overlap(
+	db: &PgPool,
+	u: &UserId,
+	new: &[WebTimeSlot],
+) -> Result<(), WebError<Value>> {
+	let mut overlapping: Vec<_> = get_timeslots(db, u, true)
+		.await?
+		.into_iter()
+		.filter(|existing| new.iter().any(|ts| timeslots_overlap(ts, existing)))
+		.collect();
+
+	overlapping.extend(overlapping_within(new).into_iter().cloned());
+
+	if !overlapping.is_empty() {
+		debug!(count = overlapping.len(), "found overlapping timeslots");
+		return Err(OverlapError::Overlapping(overlapping))?;
+	}
+
+	Ok(())
+}
+
 #[derive(Serialize)]
 pub struct TimeslotCreateReturn {
 	id: Uuid,
@@ -119,42 +138,308 @@ pub struct TimeslotCreateReturn {
 pub async fn create(
 	State(AppState { db, .. }): State<AppState>,
 	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+	Query(q): Query<OverlapQuery>,
 	Json(r): Json<TimeslotCreate>,
-) -> WebResult<TimeslotCreateReturn, &'static str> {
-	if r.timerange.start.weekday() != r.weekday {
-		return Err(TimeslotCreateError::TimerangeStartShouldBeWeekday)?;
-	}
+) -> WebResult<TimeslotCreateReturn, Value> {
+	r.validate()?;
 
-	if r.timerange.start > r.timerange.end {
-		return Err(TimeslotCreateError::TimerangeStartShouldBeBeforeEnd)?;
+	if !q.allow_overlap {
+		check_overlap(&db, &u, &[r.to_web(&u)?]).await?;
 	}
 
-	if r.time.start > r.time.end {
-		return Err(TimeslotCreateError::StartTimeShouldBeBeforeEndTime)?;
-	}
+	let audit = AuditContext::new(&u, &request_id);
+
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	let id = insert_new_timeslot(&mut tx, &u, &audit, r).await?;
 
+	tx.commit().await.context("couldn't commit transaction")?;
+
+	Ok((StatusCode::CREATED, TimeslotCreateReturn { id }).into())
+}
+
+// Doesn't validate, call `TimeslotCreate::validate` first.
+async fn insert_new_timeslot(
+	db: &mut PgConnection,
+	u: &UserId,
+	audit: &AuditContext,
+	create: TimeslotCreate,
+) -> anyhow::Result<Uuid> {
 	let id = Uuid::new_v4();
-	let ts = TimeSlot {
-		user_id: u.as_str().to_owned(),
-		id,
-		subject: r.subject,
-		students: r.students.into_iter().map(|student| student.name).collect(),
-		time: DbTime {
-			beginning: r.time.start,
-			finish: r.time.end,
-		},
-		timerange: DbTimerange {
-			beginning: r.timerange.start,
-			finish: r.timerange.end,
-		},
-		timezone: r.timezone.name().to_string(),
+	let ts = create.into_timeslot(u, id)?;
+	let snapshot = convert_ts(ts.clone()).context("invalid timeslot")?;
+
+	insert_timeslot(&mut *db, ts).await?;
+	log_timeslot(&mut *db, audit, AuditAction::Create, None, Some(&snapshot)).await?;
+
+	Ok(id)
+}
+
+#[derive(Deserialize)]
+pub struct CloneRequest {
+	timerange: Range<NaiveDate>,
+}
+
+pub enum CloneError {
+	NotFound,
+	InvalidTimeslots(Vec<CloneFailure>),
+}
+
+#[derive(Serialize)]
+pub struct CloneFailure {
+	id: Uuid,
+	error: &'static str,
+}
+
+impl From<CloneError> for WebError<Value> {
+	fn from(v: CloneError) -> WebError<Value> {
+		use CloneError::*;
+		match v {
+			NotFound => (StatusCode::NOT_FOUND, "timeslot not found".into()).into(),
+			InvalidTimeslots(failures) => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				serde_json::json!({ "invalid_timeslots": failures }),
+			)
+				.into(),
+		}
+	}
+}
+
+// Copies everything except the dates into a new timeslot.
+pub async fn clone(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+	Path(p): Path<DeleteRequest>,
+	Query(q): Query<OverlapQuery>,
+	Json(r): Json<CloneRequest>,
+) -> WebResult<TimeslotCreateReturn, Value> {
+	let Some(ts) = get_timeslot_by_id(&db, &u, p.id).await? else {
+		return Err(CloneError::NotFound)?;
 	};
 
-	insert_timeslot(&db, ts).await?;
+	let create = TimeslotCreate::from_timeslot(&ts, r.timerange);
+
+	if let Err(e) = create.validate() {
+		return Err(CloneError::InvalidTimeslots(vec![CloneFailure {
+			id: ts.id,
+			error: e.message(),
+		}]))?;
+	}
+
+	if !q.allow_overlap {
+		check_overlap(&db, &u, &[create.to_web(&u)?]).await?;
+	}
+
+	let audit = AuditContext::new(&u, &request_id);
+
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	let id = insert_new_timeslot(&mut tx, &u, &audit, create).await?;
+
+	tx.commit().await.context("couldn't commit transaction")?;
 
 	Ok((StatusCode::CREATED, TimeslotCreateReturn { id }).into())
 }
 
+#[derive(Deserialize)]
+pub struct CloneManyRequest {
+	ids: Vec<Uuid>,
+	// Each clone starts on the first occurrence of its weekday in this range.
+	timerange: Range<NaiveDate>,
+}
+
+#[derive(Serialize)]
+pub struct ClonedTimeslot {
+	source: Uuid,
+	id: Uuid,
+}
+
+// Clones all timeslots of a term at once, either all of them get cloned or none.
+pub async fn clone_many(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+	Query(q): Query<OverlapQuery>,
+	Json(r): Json<CloneManyRequest>,
+) -> WebResult<Vec<ClonedTimeslot>, Value> {
+	let mut creates = Vec::with_capacity(r.ids.len());
+	let mut failures = Vec::new();
+
+	for id in r.ids {
+		let Some(ts) = get_timeslot_by_id(&db, &u, id).await? else {
+			failures.push(CloneFailure {
+				id,
+				error: "timeslot not found",
+			});
+			continue;
+		};
+
+		let Some(start) = next_weekday(r.timerange.start, ts.weekday) else {
+			failures.push(CloneFailure {
+				id,
+				error: "timerange.start is out of range",
+			});
+			continue;
+		};
+
+		let create = TimeslotCreate::from_timeslot(&ts, start..r.timerange.end);
+
+		match create.validate() {
+			Ok(()) => creates.push((id, create)),
+			Err(e) => failures.push(CloneFailure {
+				id,
+				error: e.message(),
+			}),
+		}
+	}
+
+	if !failures.is_empty() {
+		return Err(CloneError::InvalidTimeslots(failures))?;
+	}
+
+	if !q.allow_overlap {
+		let new: Vec<_> = creates
+			.iter()
+			.map(|(_, create)| create.to_web(&u))
+			.try_collect()?;
+
+		check_overlap(&db, &u, &new).await?;
+	}
+
+	let audit = AuditContext::new(&u, &request_id);
+
+	let mut cloned = Vec::with_capacity(creates.len());
+
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	for (source, create) in creates {
+		let id = insert_new_timeslot(&mut tx, &u, &audit, create).await?;
+
+		cloned.push(ClonedTimeslot { source, id });
+	}
+
+	tx.commit().await.context("couldn't commit transaction")?;
+
+	Ok((StatusCode::CREATED, cloned).into())
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+	#[serde(default)]
+	confirm: bool,
+}
+
+#[derive(Serialize)]
+pub struct ImportPreviewItem {
+	uid: Option<String>,
+	timeslot: Option<TimeslotCreate>,
+	error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportReturn {
+	preview: Vec<ImportPreviewItem>,
+	created: Vec<Uuid>,
+}
+
+pub enum TimeslotImportError {
+	NoEvents,
+	InvalidEvents(Vec<ImportPreviewItem>),
+}
+
+impl From<TimeslotImportError> for WebError<Value> {
+	fn from(v: TimeslotImportError) -> WebError<Value> {
+		use TimeslotImportError::*;
+		match v {
+			NoEvents => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				"calendar doesn't contain any events".into(),
+			)
+				.into(),
+			InvalidEvents(preview) => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				serde_json::json!({ "invalid_events": preview }),
+			)
+				.into(),
+		}
+	}
+}
+
+// Without `confirm` this only returns a preview of the timeslots, which would be created.
+pub async fn import(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+	Query(q): Query<ImportQuery>,
+	Query(overlap): Query<OverlapQuery>,
+	body: String,
+) -> WebResult<ImportReturn, Value> {
+	let preview: Vec<_> = parse_calendar(&body)
+		.into_iter()
+		.map(|event| match event.result {
+			Ok(ts) => {
+				let error = ts.validate().err().map(|e| e.message().to_string());
+
+				ImportPreviewItem {
+					uid: event.uid,
+					timeslot: Some(ts),
+					error,
+				}
+			}
+			Err(e) => ImportPreviewItem {
+				uid: event.uid,
+				timeslot: None,
+				error: Some(e.to_string()),
+			},
+		})
+		.collect();
+
+	if preview.is_empty() {
+		return Err(TimeslotImportError::NoEvents)?;
+	}
+
+	if !q.confirm {
+		return Ok(ImportReturn {
+			preview,
+			created: Vec::new(),
+		}
+		.into());
+	}
+
+	if preview.iter().any(|i| i.error.is_some()) {
+		return Err(TimeslotImportError::InvalidEvents(preview))?;
+	}
+
+	if !overlap.allow_overlap {
+		let new: Vec<_> = preview
+			.iter()
+			.filter_map(|i| i.timeslot.as_ref())
+			.map(|ts| ts.to_web(&u))
+			.try_collect()?;
+
+		check_overlap(&db, &u, &new).await?;
+	}
+
+	let mut created = Vec::with_capacity(preview.len());
+
+	let audit = AuditContext::new(&u, &request_id);
+
+	// Either all timeslots get imported or none.
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	for ts in preview.iter().filter_map(|i| i.timeslot.clone()) {
+		let id = insert_new_timeslot(&mut tx, &u, &audit, ts).await?;
+
+		created.push(id);
+	}
+
+	tx.commit().await.context("couldn't commit transaction")?;
+
+	Ok((StatusCode::CREATED, ImportReturn { preview, created }).into())
+}
+
 #[derive(Deserialize)]
 pub struct DeleteRequest {
 	pub id: Uuid,
@@ -177,30 +462,229 @@ impl From<DeleteError> for WebError<&'static str> {
 pub async fn delete(
 	State(AppState { db, .. }): State<AppState>,
 	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
 	Path(r): Path<DeleteRequest>,
 ) -> WebResult<&'static str, &'static str> {
-	if delete_timeslot_by_id(&db, &u, r.id).await? == 0 {
-		Err(DeleteError::NotFound)?;
+	let audit = AuditContext::new(&u, &request_id);
+
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	// Deleting the timeslot deletes its entries, so they have to be logged as well.
+	let entries = get_entries_by_timeslot_id(&mut *tx, &u, r.id).await?;
+
+	let Some(ts) = delete_timeslot_by_id(&mut tx, &u, r.id).await? else {
+		return Err(DeleteError::NotFound)?;
+	};
+
+	log_timeslot(&mut *tx, &audit, AuditAction::Delete, Some(&ts), None).await?;
+
+	for e in &entries {
+		log_entry(&mut *tx, &audit, AuditAction::Delete, Some(e), None).await?;
 	}
 
+	tx.commit().await.context("couldn't commit transaction")?;
+
 	Ok("deleted".into())
 }
 
+pub enum RestoreError {
+	NotFound,
+}
+
+impl From<RestoreError> for WebError<&'static str> {
+	fn from(v: RestoreError) -> Self {
+		match v {
+			RestoreError::NotFound => {
+				(StatusCode::NOT_FOUND, "couldn't find deleted timeslot").into()
+			}
+		}
+	}
+}
+
+pub async fn restore(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+	Path(r): Path<DeleteRequest>,
+) -> WebResult<&'static str, &'static str> {
+	let audit = AuditContext::new(&u, &request_id);
+
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	let Some((ts, entries)) = restore_timeslot_by_id(&mut tx, &u, r.id).await? else {
+		return Err(RestoreError::NotFound)?;
+	};
+
+	log_timeslot(&mut *tx, &audit, AuditAction::Restore, None, Some(&ts)).await?;
+
+	for e in &entries {
+		log_entry(&mut *tx, &audit, AuditAction::Restore, None, Some(e)).await?;
+	}
+
+	tx.commit().await.context("couldn't commit transaction")?;
+
+	Ok("restored".into())
+}
+
+pub enum ArchiveError {
+	NotFound,
+}
+
+impl From<ArchiveError> for WebError<&'static str> {
+	fn from(v: ArchiveError) -> Self {
+		match v {
+			ArchiveError::NotFound => (StatusCode::NOT_FOUND, "timeslot not found").into(),
+		}
+	}
+}
+
+async fn set_archived(
+	db: &PgPool,
+	u: &UserId,
+	request_id: &RequestId,
+	id: Uuid,
+	archived: bool,
+) -> WebResult<&'static str, &'static str> {
+	let audit = AuditContext::new(u, request_id);
+
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	let Some(before) = get_timeslot_by_id(&mut *tx, u, id).await? else {
+		return Err(ArchiveError::NotFound)?;
+	};
+
+	let Some(after) = set_timeslot_archived(&mut *tx, u, id, archived).await? else {
+		return Err(ArchiveError::NotFound)?;
+	};
+
+	log_timeslot(
+		&mut *tx,
+		&audit,
+		AuditAction::Update,
+		Some(&before),
+		Some(&after),
+	)
+	.await?;
+
+	tx.commit().await.context("couldn't commit transaction")?;
+
+	Ok("success".into())
+}
+
+pub async fn archive(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+	Path(r): Path<DeleteRequest>,
+) -> WebResult<&'static str, &'static str> {
+	set_archived(&db, &u, &request_id, r.id, true).await
+}
+
+// Unarchived timeslots aren't archived automatically again.
+pub async fn unarchive(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+	Path(r): Path<DeleteRequest>,
+) -> WebResult<&'static str, &'static str> {
+	set_archived(&db, &u, &request_id, r.id, false).await
+}
+
+#[derive(Deserialize)]
+pub struct HourlyRatePath {
+	pub id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct HourlyRateRequest {
+	// In cents, `None` removes the rate
+	hourly_rate: Option<u32>,
+}
+
+pub enum HourlyRateError {
+	NotFound,
+}
+
+impl From<HourlyRateError> for WebError<&'static str> {
+	fn from(v: HourlyRateError) -> Self {
+		match v {
+			HourlyRateError::NotFound => (StatusCode::NOT_FOUND, "timeslot not found").into(),
+		}
+	}
+}
+
+pub async fn set_hourly_rate(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Extension(request_id): Extension<RequestId>,
+	Path(p): Path<HourlyRatePath>,
+	Json(r): Json<HourlyRateRequest>,
+) -> WebResult<&'static str, &'static str> {
+	let hourly_rate = r.hourly_rate.map(i32::try_from).transpose()?;
+
+	let audit = AuditContext::new(&u, &request_id);
+
+	let mut tx = db.begin().await.context("couldn't begin transaction")?;
+
+	let Some(before) = get_timeslot_by_id(&mut *tx, &u, p.id).await? else {
+		return Err(HourlyRateError::NotFound)?;
+	};
+
+	let Some(after) = update_timeslot_hourly_rate(&mut *tx, &u, p.id, hourly_rate).await? else {
+		return Err(HourlyRateError::NotFound)?;
+	};
+
+	log_timeslot(
+		&mut *tx,
+		&audit,
+		AuditAction::Update,
+		Some(&before),
+		Some(&after),
+	)
+	.await?;
+
+	tx.commit().await.context("couldn't commit transaction")?;
+
+	Ok("success".into())
+}
+
 #[derive(Deserialize)]
 pub struct ExportRequest {
-	start_year: i32,
-	start_week: u32,
-	end_year: i32,
-	end_week: u32,
+	start_year: Option<i32>,
+	start_week: Option<u32>,
+	end_year: Option<i32>,
+	end_week: Option<u32>,
+	start_date: Option<NaiveDate>,
+	end_date: Option<NaiveDate>,
+	#[serde(default)]
+	group_by: GroupBy,
 	#[serde(default)]
 	allow_incomplete: bool,
+	#[serde(default)]
+	locale: Locale,
+	// Comma seperated, since `Query` doesn't support repeated keys.
+	timeslot_ids: Option<String>,
+	subject: Option<String>,
 }
 
 pub enum ExportError {
 	InvalidWeekYear,
+	InvalidDateRange,
+	InvalidTimeslotIds,
+	UnknownTimeslotIds(Vec<Uuid>),
+	UnknownSubject(String),
 	MissingEntries(Vec<(String, Uuid)>),
 }
 
+impl From<DateRangeError> for ExportError {
+	fn from(v: DateRangeError) -> ExportError {
+		match v {
+			DateRangeError::InvalidWeekYear => ExportError::InvalidWeekYear,
+			DateRangeError::InvalidDateRange => ExportError::InvalidDateRange,
+		}
+	}
+}
+
 impl From<ExportError> for WebError<Value> {
 	fn from(v: ExportError) -> WebError<Value> {
 		use ExportError::*;
@@ -208,6 +692,26 @@ impl From<ExportError> for WebError<Value> {
 			InvalidWeekYear => {
 				(StatusCode::UNPROCESSABLE_ENTITY, "invalid_week/year".into()).into()
 			}
+			InvalidDateRange => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				"invalid date range".into(),
+			)
+				.into(),
+			InvalidTimeslotIds => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				"invalid timeslot_ids".into(),
+			)
+				.into(),
+			UnknownTimeslotIds(ids) => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				serde_json::json!({ "unknown_timeslot_ids": ids }),
+			)
+				.into(),
+			UnknownSubject(subject) => (
+				StatusCode::UNPROCESSABLE_ENTITY,
+				serde_json::json!({ "unknown_subject": subject }),
+			)
+				.into(),
 			MissingEntries(entries) => {
 				let entries_json: Vec<_> = entries
 					.into_iter()
@@ -224,31 +728,48 @@ impl From<ExportError> for WebError<Value> {
 	}
 }
 
-// TODO: some of this logic should probably be extracted
 pub async fn export(
 	State(AppState { db, .. }): State<AppState>,
 	Extension(u): Extension<UserId>,
 	Query(q): Query<ExportRequest>,
 ) -> WebResult<String, Value> {
-	let Some(start) = create_isoweek(q.start_year, q.start_week) else {
-		return Err(ExportError::InvalidWeekYear)?;
-	};
+	let Range { start, end } = date_range(
+		q.start_date,
+		q.end_date,
+		q.start_year.zip(q.start_week),
+		q.end_year.zip(q.end_week),
+	)
+	.map_err(ExportError::from)?;
 
-	let Some(end) = create_isoweek(q.end_year, q.end_week) else {
-		return Err(ExportError::InvalidWeekYear)?;
-	};
+	let timeslot_ids = q
+		.timeslot_ids
+		.as_deref()
+		.map(|ids| parse_timeslot_ids(ids).ok_or(ExportError::InvalidTimeslotIds))
+		.transpose()?;
 
-	let mut user_timeslots = get_timeslots(&db, &u).await?;
+	// Archived timeslots are still exported.
+	let mut user_timeslots = get_timeslots(&db, &u, true).await?;
 
-	// Make sure we list timeslots in order in export
-	user_timeslots.sort_by(|a, b| {
-		a.timerange
-			.start
-			.weekday()
-			.num_days_from_monday()
-			.cmp(&b.timerange.start.weekday().num_days_from_monday())
-			.then(a.time.start.cmp(&b.time.start))
-	});
+	// A typo shouldn't silently result in an empty or partial export.
+	if let Some(ids) = timeslot_ids.as_deref() {
+		let unknown = unknown_timeslot_ids(ids, &user_timeslots);
+
+		if !unknown.is_empty() {
+			return Err(ExportError::UnknownTimeslotIds(unknown))?;
+		}
+	}
+
+	if let Some(subject) = &q.subject {
+		if !user_timeslots.iter().any(|ts| &ts.subject == subject) {
+			return Err(ExportError::UnknownSubject(subject.clone()))?;
+		}
+	}
+
+	// Only selected timeslots are checked for missing entries.
+	user_timeslots
+		.retain(|ts| includes_timeslot(ts, timeslot_ids.as_deref(), q.subject.as_deref()));
+
+	sort_timeslots(&mut user_timeslots);
 
 	check_object_belong_to_userid(user_timeslots.iter(), &u)?;
 
@@ -284,12 +805,13 @@ pub async fn export(
 		.collect::<Vec<_>>()
 		.await;
 
-	// BtreeMap, because we need ordering
-	// TODO: Vec<Student> should probably be Arc<[Student]> to save allocations.
-	let mut week_map: BTreeMap<IsoWeek, Vec<(WebEntry, Vec<String>)>> = BTreeMap::new();
+	let mut group_map = ExportGroups::new();
 
 	let mut missing_entry_errors: Option<Vec<(String, uuid::Uuid)>> = None;
 
+	// Kept in the same order as the timeslots.
+	let mut timeslot_totals: Vec<(String, ExportTotals)> = Vec::new();
+
 	for res in entry_results {
 		let (entries, ts, expected_count) = res.unwrap()?;
 
@@ -317,20 +839,9 @@ pub async fn export(
 			continue;
 		}
 
-		for e in entries {
-			let iso_week = get_time_from_index_and_timeslot(&ts, e.index)
-				.context(format!(
-					"unable to get time from from entry: {}",
-					e.identifier()
-				))?
-				.iso_week();
+		let totals = group_entries(&mut group_map, q.group_by, &ts, entries)?;
 
-			if let Some(week_entries) = week_map.get_mut(&iso_week) {
-				week_entries.push((e, ts.students.clone()));
-			} else {
-				week_map.insert(iso_week, [(e, ts.students.clone())].into());
-			}
-		}
+		timeslot_totals.push((q.locale.timeslot_label(&ts), totals));
 	}
 
 	if let Some(e) = missing_entry_errors {
@@ -339,14 +850,20 @@ pub async fn export(
 
 	let mut output = String::new();
 
-	for (w, entries) in &week_map {
-		writeln!(output, "KW{}", w.week())?;
+	for (g, entries) in &group_map {
+		writeln!(output, "{}", q.locale.group_heading(*g))?;
 		for (e, students) in entries {
 			debug!(ts=%e.timeslot_id, idx=e.index, "exporting entry");
-			writeln!(output, "{}", format_entry(e.state, &e.students, students))?;
+			writeln!(
+				output,
+				"{}",
+				format_entry(e.state, &e.students, students, q.locale)
+			)?;
 		}
 	}
 
+	write_totals(&mut output, q.locale, &timeslot_totals)?;
+
 	Ok(output.into())
 }
 #[derive(Serialize)]
@@ -362,36 +879,16 @@ pub async fn information(
 	State(AppState { db, .. }): State<AppState>,
 	Extension(u): Extension<UserId>,
 ) -> WebResult<InformationV3Response, &'static str> {
-	let timeslots = get_timeslots(&db, &u).await?;
-
-	let next_missing = futures_util::future::join_all(
-		timeslots
-			.iter()
-			.cloned()
-			.map(|ts| {
-				let db_task = db.clone();
-				let u_task = u.clone();
-
-				tokio::spawn(async move {
-					return (next_entry_timeslot(&ts), missing_entries(&db_task, &u_task, &ts).await)
-				})
-			})
-			.map(|fut| fut.map(Result::unwrap)),
-	)
-	.await;
+	let timeslots = get_timeslots(&db, &u, false).await?;
 
-	assert!(
-		timeslots.len() == next_missing.len(),
-		"length of next missing not equal to timeslots"
-	);
+	let missing_entries = missing_entries_timeslots(&db, &u, &timeslots).await?;
 
 	let mut res: Vec<_> = timeslots
 		.into_iter()
-		.zip(next_missing)
-		.map(|(ts, (next_res, missing_res))| {
-			let next = next_res?;
+		.map(|ts| {
+			let next = next_entry_timeslot(&ts)?;
 
-			let missing = missing_res?.len().try_into()?;
+			let missing = missing_entries.get(&ts.id).map_or(0, Vec::len).try_into()?;
 
 			anyhow::Result::<_>::Ok(InformationV3ResponseItem { ts, next, missing })
 		})
@@ -0,0 +1,28 @@
+use axum::extract::State;
+use axum::Extension;
+
+use serde::Serialize;
+
+use crate::api::util::prelude::*;
+use crate::api::AppState;
+use crate::auth::UserId;
+use crate::db::queries::trash::{
+	get_deleted_entries, get_deleted_timeslots, DeletedEntry, DeletedTimeslot,
+};
+
+#[derive(Serialize)]
+pub struct TrashReturn {
+	timeslots: Vec<DeletedTimeslot>,
+	entries: Vec<DeletedEntry>,
+}
+
+pub async fn query(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+) -> WebResult<TrashReturn, &'static str> {
+	Ok(TrashReturn {
+		timeslots: get_deleted_timeslots(&db, &u).await?,
+		entries: get_deleted_entries(&db, &u).await?,
+	}
+	.into())
+}
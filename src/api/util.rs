@@ -23,6 +23,9 @@ impl<T: Into<A>, E: Into<B>, A, B> TransposeResult<A, B> for Result<T, E> {
 #[derive(Serialize)]
 struct Msg<T> {
 	msg: T,
+	// Cursor of the next page for paginated responses
+	#[serde(skip_serializing_if = "Option::is_none")]
+	next: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -33,11 +36,26 @@ struct Error<T> {
 pub struct WebSuccess<M: Serialize> {
 	pub msg: M,
 	pub status: axum::http::StatusCode,
+	pub next: Option<String>,
+}
+
+impl<M: Serialize> WebSuccess<M> {
+	pub fn with_next(mut self, next: Option<impl ToString>) -> Self {
+		self.next = next.map(|n| n.to_string());
+		self
+	}
 }
 
 impl<M: Serialize> IntoResponse for WebSuccess<M> {
 	fn into_response(self) -> axum::response::Response {
-		(self.status, Json(Msg { msg: self.msg })).into_response()
+		(
+			self.status,
+			Json(Msg {
+				msg: self.msg,
+				next: self.next,
+			}),
+		)
+			.into_response()
 	}
 }
 
@@ -46,6 +64,7 @@ impl<M: Serialize> From<M> for WebSuccess<M> {
 		WebSuccess {
 			msg: value,
 			status: StatusCode::OK,
+			next: None,
 		}
 	}
 }
@@ -55,6 +74,7 @@ impl<M: Serialize> From<(StatusCode, M)> for WebSuccess<M> {
 		WebSuccess {
 			msg: value.1,
 			status: value.0,
+			next: None,
 		}
 	}
 }
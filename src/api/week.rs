@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Extension;
+
+use chrono::{DateTime, FixedOffset};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::logic::check_object_belong_to_userid;
+use crate::api::logic::entry::get_time_from_index_and_timeslot;
+use crate::api::logic::timeslot::get_index_range_timeslot;
+use crate::api::util::prelude::*;
+use crate::api::AppState;
+use crate::auth::UserId;
+use crate::db::model::WebEntry;
+use crate::db::queries::entry::get_entries_for_occurrences;
+use crate::db::queries::timeslot::get_timeslots;
+use crate::util::{create_isoweek, isoweek_date_range};
+
+#[derive(Deserialize)]
+pub struct WeekQuery {
+	year: i32,
+	week: u32,
+}
+
+pub enum WeekError {
+	InvalidWeekYear,
+}
+
+impl From<WeekError> for WebError<&'static str> {
+	fn from(v: WeekError) -> WebError<&'static str> {
+		use WeekError::*;
+		match v {
+			InvalidWeekYear => (StatusCode::UNPROCESSABLE_ENTITY, "invalid week/year").into(),
+		}
+	}
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum OccurrenceEntry {
+	Filled { entry: WebEntry },
+	Unfilled,
+}
+
+#[derive(Serialize)]
+pub struct Occurrence {
+	timeslot_id: Uuid,
+	subject: String,
+	index: u32,
+	timestamp: DateTime<FixedOffset>,
+	#[serde(flatten)]
+	entry: OccurrenceEntry,
+}
+
+// Every occurrence of every timeslot in the week, so the frontend doesn't have to query each timeslot.
+pub async fn query(
+	State(AppState { db, .. }): State<AppState>,
+	Extension(u): Extension<UserId>,
+	Query(q): Query<WeekQuery>,
+) -> WebResult<Vec<Occurrence>, &'static str> {
+	let week = create_isoweek(q.year, q.week).ok_or(WeekError::InvalidWeekYear)?;
+	let range = isoweek_date_range(week..week).ok_or(WeekError::InvalidWeekYear)?;
+
+	let timeslots = get_timeslots(&db, &u, true).await?;
+
+	check_object_belong_to_userid(timeslots.iter(), &u)?;
+
+	let mut occurrences = Vec::new();
+
+	for ts in &timeslots {
+		let Some(indices) = get_index_range_timeslot(ts, range.clone()) else {
+			continue;
+		};
+
+		for index in indices.start..=indices.end {
+			if let Some(timestamp) = get_time_from_index_and_timeslot(ts, index) {
+				occurrences.push((ts, index, timestamp.fixed_offset()));
+			}
+		}
+	}
+
+	let (timeslot_ids, indexes): (Vec<_>, Vec<_>) = occurrences
+		.iter()
+		.map(|(ts, index, _)| Ok((ts.id, i32::try_from(*index)?)))
+		.collect::<anyhow::Result<Vec<_>>>()?
+		.into_iter()
+		.unzip();
+
+	let entries = get_entries_for_occurrences(&db, &u, &timeslot_ids, &indexes).await?;
+
+	check_object_belong_to_userid(entries.iter(), &u)?;
+
+	let mut entries: HashMap<_, _> = entries
+		.into_iter()
+		.map(|e| ((e.timeslot_id, e.index), e))
+		.collect();
+
+	let mut res: Vec<_> = occurrences
+		.into_iter()
+		.map(|(ts, index, timestamp)| Occurrence {
+			timeslot_id: ts.id,
+			subject: ts.subject.clone(),
+			index,
+			timestamp,
+			entry: match entries.remove(&(ts.id, index)) {
+				Some(entry) => OccurrenceEntry::Filled { entry },
+				None => OccurrenceEntry::Unfilled,
+			},
+		})
+		.collect();
+
+	res.sort_by_key(|o| o.timestamp);
+
+	Ok(res.into())
+}
@@ -4,6 +4,7 @@ use base64::Engine;
 use chrono::{Duration, Utc};
 use openid::{Client, Options};
 use rand::RngCore;
+use sha2::{Digest, Sha256};
 use sqlx::PgPool;
 use url::Url;
 
@@ -31,6 +32,11 @@ impl UserId {
 	pub fn as_str(&self) -> &str {
 		&self.0
 	}
+
+	// Only for user ids read from the database, e.g. in background tasks.
+	pub fn from_db(user_id: String) -> UserId {
+		UserId(user_id.into())
+	}
 }
 
 impl Authenticator {
@@ -132,6 +138,25 @@ fn verify_session_expiry(session: &Session) -> Result<(), ()> {
 	Ok(())
 }
 
+// Feed tokens are random, so a plain hash is sufficient.
+pub fn hash_feed_token(token: &str) -> Vec<u8> {
+	Sha256::digest(token.as_bytes()).to_vec()
+}
+
+pub async fn verify_feed_token(db: &PgPool, token: &str) -> anyhow::Result<Option<UserId>> {
+	let user_id = queries::feed_token::get_user_id(db, &hash_feed_token(token)).await?;
+
+	Ok(user_id.map(|u| UserId(u.into())))
+}
+
+// Without padding, since the token ends up in a query parameter.
+pub fn gen_feed_token() -> String {
+	let mut token_bytes = [0u8; 32];
+	rand::thread_rng().fill_bytes(&mut token_bytes);
+
+	base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(token_bytes)
+}
+
 fn gen_nonce() -> String {
 	let mut state_bytes = [0u8; 64];
 	rand::thread_rng().fill_bytes(&mut state_bytes);
@@ -8,6 +8,15 @@ pub struct Config {
 	pub auth: Authorization,
 	pub hosturl: SocketAddr,
 	pub tls: Option<Tls>,
+	#[serde(default)]
+	pub deletion: Deletion,
+	#[serde(default)]
+	pub trash: Trash,
+	#[serde(default)]
+	pub retention: Retention,
+	#[serde(default)]
+	pub redaction: Redaction,
+	pub encryption: Option<Encryption>,
 }
 
 #[derive(Deserialize)]
@@ -28,3 +37,60 @@ pub struct Tls {
 	pub certpath: String,
 	pub keypath: String,
 }
+
+// Field names can't contain underscores, since they're used as the env separator.
+#[derive(Deserialize)]
+pub struct Deletion {
+	// Days until a requested account deletion is carried out
+	pub gracedays: u32,
+}
+
+impl Default for Deletion {
+	fn default() -> Self {
+		Deletion { gracedays: 14 }
+	}
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct Trash {
+	// Days until deleted timeslots and entries are removed permanently
+	pub days: u32,
+}
+
+impl Default for Trash {
+	fn default() -> Self {
+		Trash { days: 30 }
+	}
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionMode {
+	#[default]
+	Delete,
+	// Replaces student names, but keeps the timeslots and entries
+	Anonymise,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+pub struct Retention {
+	// Months after the end of a timeslot, until its data expires. `None` disables the retention policy.
+	pub months: Option<u32>,
+	#[serde(default)]
+	pub mode: RetentionMode,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Redaction {
+	// Logs raw student names, only meant for development
+	#[serde(default)]
+	pub disabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct Encryption {
+	// Comma seperated list of `keyid:base64 encoded 256 bit key`
+	pub keys: String,
+	// Id of the key used to encrypt new data, older keys are only used for decryption
+	pub current: String,
+}
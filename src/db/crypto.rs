@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::Context;
+use base64::Engine;
+
+use crate::configuration::Encryption;
+
+const PREFIX: &str = "enc";
+const NONCE_LEN: usize = 12;
+
+static CIPHER: OnceLock<Cipher> = OnceLock::new();
+
+// Encrypted values are stored as `enc:keyid:base64(nonce + ciphertext)`,
+// so values encrypted with older keys can still be decrypted after rotating keys.
+pub struct Cipher {
+	current: String,
+	keys: HashMap<String, Aes256Gcm>,
+}
+
+impl Cipher {
+	pub fn new(cfg: &Encryption) -> anyhow::Result<Cipher> {
+		let engine = base64::engine::general_purpose::STANDARD;
+
+		let keys = cfg
+			.keys
+			.split(',')
+			.map(|k| {
+				let (id, key) = k
+					.trim()
+					.split_once(':')
+					.context("encryption key should be formatted as `keyid:key`")?;
+
+				let key = engine
+					.decode(key)
+					.context("encryption key isn't valid base64")?;
+				let cipher = Aes256Gcm::new_from_slice(&key)
+					.map_err(|_| anyhow::anyhow!("encryption key {id} isn't 256 bits long"))?;
+
+				Ok((id.to_string(), cipher))
+			})
+			.collect::<anyhow::Result<HashMap<_, _>>>()?;
+
+		anyhow::ensure!(
+			keys.contains_key(&cfg.current),
+			"current encryption key {} isn't configured",
+			cfg.current
+		);
+
+		Ok(Cipher {
+			current: cfg.current.clone(),
+			keys,
+		})
+	}
+
+	pub fn encrypt(&self, plain: &str) -> anyhow::Result<String> {
+		let cipher = &self.keys[&self.current];
+
+		let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+		let ciphertext = cipher
+			.encrypt(&nonce, plain.as_bytes())
+			.map_err(|_| anyhow::anyhow!("couldn't encrypt value"))?;
+
+		let mut data = nonce.to_vec();
+		data.extend(ciphertext);
+
+		Ok(format!(
+			"{PREFIX}:{}:{}",
+			self.current,
+			base64::engine::general_purpose::STANDARD.encode(data)
+		))
+	}
+
+	pub fn decrypt(&self, value: &str) -> anyhow::Result<String> {
+		let Some((id, data)) = parse(value) else {
+			return Ok(value.to_string());
+		};
+
+		let cipher = self
+			.keys
+			.get(id)
+			.with_context(|| format!("encryption key {id} isn't configured"))?;
+
+		let data = base64::engine::general_purpose::STANDARD
+			.decode(data)
+			.context("encrypted value isn't valid base64")?;
+
+		anyhow::ensure!(data.len() > NONCE_LEN, "encrypted value is too short");
+
+		let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+
+		let plain = cipher
+			.decrypt(Nonce::from_slice(nonce), ciphertext)
+			.map_err(|_| anyhow::anyhow!("couldn't decrypt value"))?;
+
+		Ok(String::from_utf8(plain)?)
+	}
+
+	// Plaintext values and values encrypted with old keys need to be encrypted (again).
+	pub fn is_current(&self, value: &str) -> bool {
+		parse(value).is_some_and(|(id, _)| id == self.current)
+	}
+}
+
+fn parse(value: &str) -> Option<(&str, &str)> {
+	let rest = value.strip_prefix(PREFIX)?.strip_prefix(':')?;
+
+	rest.split_once(':')
+}
+
+pub fn init(cfg: Option<&Encryption>) -> anyhow::Result<()> {
+	let Some(cfg) = cfg else {
+		return Ok(());
+	};
+
+	if CIPHER.set(Cipher::new(cfg)?).is_err() {
+		anyhow::bail!("encryption was already initialised");
+	}
+
+	Ok(())
+}
+
+pub fn cipher() -> Option<&'static Cipher> {
+	CIPHER.get()
+}
+
+// Without a configured key values are stored as plaintext.
+pub fn encrypt(plain: &str) -> anyhow::Result<String> {
+	match cipher() {
+		Some(c) => c.encrypt(plain),
+		None => Ok(plain.to_string()),
+	}
+}
+
+// Plaintext values are returned as is, so unencrypted rows can still be read.
+pub fn decrypt(value: &str) -> anyhow::Result<String> {
+	match cipher() {
+		Some(c) => c.decrypt(value),
+		None if parse(value).is_some() => {
+			anyhow::bail!("found encrypted value, but encryption isn't configured")
+		}
+		None => Ok(value.to_string()),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::configuration::Encryption;
+
+	use super::Cipher;
+
+	#[test]
+	fn test_cipher_rotation() {
+		let old = Cipher::new(&Encryption {
+			keys: "1:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".into(),
+			current: "1".into(),
+		})
+		.unwrap();
+
+		let new = Cipher::new(&Encryption {
+			keys: "1:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=,2:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=".into(),
+			current: "2".into(),
+		})
+		.unwrap();
+
+		let encrypted = old.encrypt("Erika").unwrap();
+
+		assert!(!encrypted.contains("Erika"));
+		assert_eq!(old.decrypt(&encrypted).unwrap(), "Erika");
+		assert_eq!(new.decrypt(&encrypted).unwrap(), "Erika");
+		assert!(old.is_current(&encrypted));
+		assert!(!new.is_current(&encrypted));
+		assert_eq!(new.decrypt("plain").unwrap(), "plain");
+	}
+}
@@ -7,10 +7,13 @@ use sqlx::{
 
 use crate::configuration::Config;
 
+pub mod crypto;
 pub mod model;
 pub mod queries;
 
 pub async fn get_pool(cfg: &Config) -> PgPool {
+	crypto::init(cfg.encryption.as_ref()).expect("Invalid encryption configuration");
+
 	let pg_options = PgConnectOptions::from_str(&cfg.database.uri)
 		.expect("Invalid database URI")
 		.application_name("lelo_backend");
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::ops::Range;
 use std::str::FromStr;
 
@@ -6,6 +7,7 @@ use chrono::NaiveDate;
 use chrono::NaiveTime;
 use chrono::Weekday;
 use chrono_tz::Tz;
+use itertools::Itertools;
 
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::Type;
@@ -13,11 +15,23 @@ use tracing::error;
 
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Debug)]
+use crate::db::crypto;
+use crate::util::logging::Redacted;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
 pub struct Student {
 	pub name: String,
 }
 
+// Debug is implemented by hand for every type containing student names, so they don't end up in logs.
+impl fmt::Debug for Student {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Student")
+			.field("name", &Redacted(&self.name))
+			.finish()
+	}
+}
+
 #[derive(Serialize, Deserialize, Debug, Hash, PartialEq, Eq, Clone, Copy, Type)]
 #[sqlx(type_name = "student_status")]
 #[sqlx(rename_all = "lowercase")]
@@ -27,13 +41,22 @@ pub enum StudentStatus {
 	Missing,
 }
 
-#[derive(Serialize, Deserialize, Debug, Type)]
+#[derive(Serialize, Deserialize, Clone, Type)]
 #[sqlx(type_name = "student_state")]
 pub struct StudentState {
 	pub student: String,
 	pub status: StudentStatus,
 }
 
+impl fmt::Debug for StudentState {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("StudentState")
+			.field("student", &Redacted(&self.student))
+			.field("status", &self.status)
+			.finish()
+	}
+}
+
 impl sqlx::postgres::PgHasArrayType for StudentState {
 	fn array_type_info() -> sqlx::postgres::PgTypeInfo {
 		sqlx::postgres::PgTypeInfo::with_name("_student_state")
@@ -57,21 +80,21 @@ impl TryFrom<u8> for StudentStatus {
 	}
 }
 
-#[derive(Debug, sqlx::Type)]
+#[derive(Debug, Clone, sqlx::Type)]
 #[sqlx(type_name = "timeslot_time")]
 pub struct DbTime {
 	pub beginning: NaiveTime,
 	pub finish: NaiveTime,
 }
 
-#[derive(Debug, sqlx::Type)]
+#[derive(Debug, Clone, sqlx::Type)]
 #[sqlx(type_name = "timeslot_range")]
 pub struct DbTimerange {
 	pub beginning: NaiveDate,
 	pub finish: NaiveDate,
 }
 
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct TimeSlot {
 	pub user_id: String,
 	pub id: Uuid,
@@ -80,9 +103,29 @@ pub struct TimeSlot {
 	pub time: DbTime,
 	pub timerange: DbTimerange,
 	pub timezone: String,
+	pub hourly_rate: Option<i32>,
+	pub archived: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, Type)]
+impl fmt::Debug for TimeSlot {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let students: Vec<_> = self.students.iter().map(|s| Redacted(s.as_str())).collect();
+
+		f.debug_struct("TimeSlot")
+			.field("user_id", &self.user_id)
+			.field("id", &self.id)
+			.field("subject", &self.subject)
+			.field("students", &students)
+			.field("time", &self.time)
+			.field("timerange", &self.timerange)
+			.field("timezone", &self.timezone)
+			.field("hourly_rate", &self.hourly_rate)
+			.field("archived", &self.archived)
+			.finish()
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Type)]
 #[sqlx(type_name = "entry_state")]
 #[sqlx(rename_all = "lowercase")]
 pub enum EntryState {
@@ -108,7 +151,7 @@ pub enum OldEntryState {
 	InvalidData,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Entry {
 	pub user_id: String,
 	pub index: i32,
@@ -127,6 +170,10 @@ pub struct WebTimeSlot {
 	pub timerange: Range<NaiveDate>,
 	pub weekday: Weekday,
 	pub timezone: Tz,
+	// In cents
+	pub hourly_rate: Option<u32>,
+	#[serde(default)]
+	pub archived: bool,
 }
 
 pub fn convert_ts(ts: TimeSlot) -> Option<WebTimeSlot> {
@@ -143,15 +190,33 @@ pub fn convert_ts(ts: TimeSlot) -> Option<WebTimeSlot> {
 
 	let weekday = ts.timerange.beginning.weekday();
 
+	let students = match ts.students.iter().map(|s| crypto::decrypt(s)).try_collect() {
+		Ok(s) => s,
+		Err(e) => {
+			error!(%e, "couldn't decrypt students in db");
+			return None;
+		}
+	};
+
+	let hourly_rate = match ts.hourly_rate.map(u32::try_from).transpose() {
+		Ok(r) => r,
+		Err(e) => {
+			error!(%e, "invalid hourly rate data in db");
+			return None;
+		}
+	};
+
 	Some(WebTimeSlot {
 		user_id: ts.user_id,
 		id: ts.id,
 		subject: ts.subject,
-		students: ts.students,
+		students,
 		time,
 		timerange,
 		weekday,
 		timezone,
+		hourly_rate,
+		archived: ts.archived.unwrap_or(false),
 	})
 }
 
@@ -173,12 +238,30 @@ pub fn convert_entry(e: Entry) -> Option<WebEntry> {
 		}
 	};
 
+	let students = match e
+		.students
+		.into_iter()
+		.map(|s| {
+			Ok(StudentState {
+				student: crypto::decrypt(&s.student)?,
+				status: s.status,
+			})
+		})
+		.collect::<anyhow::Result<_>>()
+	{
+		Ok(s) => s,
+		Err(e) => {
+			error!(%e, "couldn't decrypt students in db");
+			return None;
+		}
+	};
+
 	Some(WebEntry {
 		user_id: e.user_id,
 		index,
 		timeslot_id: e.timeslot_id,
 		state: e.state_enum,
-		students: e.students,
+		students,
 	})
 }
 
@@ -206,3 +289,32 @@ impl HasUserId for WebEntry {
 		format!("entry: {}-{}", self.timeslot_id, self.index)
 	}
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+	use chrono::{NaiveDate, NaiveTime, Weekday};
+	use uuid::Uuid;
+
+	use super::WebTimeSlot;
+
+	pub(crate) fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+		NaiveDate::from_ymd_opt(y, m, d).unwrap()
+	}
+
+	// Weekly on mondays from 2024-01-01 until 2024-03-25, so indices 0 to 12.
+	pub(crate) fn timeslot() -> WebTimeSlot {
+		WebTimeSlot {
+			user_id: "hello".into(),
+			id: Uuid::new_v4(),
+			subject: "math".into(),
+			students: vec!["a".into()],
+			time: NaiveTime::from_hms_opt(14, 0, 0).unwrap()
+				..NaiveTime::from_hms_opt(15, 0, 0).unwrap(),
+			timerange: date(2024, 1, 1)..date(2024, 3, 25),
+			weekday: Weekday::Mon,
+			timezone: chrono_tz::Europe::Berlin,
+			hourly_rate: None,
+			archived: false,
+		}
+	}
+}
@@ -0,0 +1,97 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::auth::UserId;
+
+pub struct AccountDeletion {
+	pub requested: DateTime<Utc>,
+	pub scheduled: DateTime<Utc>,
+}
+
+// Keeps the existing schedule, if the deletion was already requested.
+pub async fn schedule_deletion(
+	db: &PgPool,
+	u: &UserId,
+	scheduled: DateTime<Utc>,
+) -> anyhow::Result<AccountDeletion> {
+	sqlx::query!(
+		"INSERT INTO account_deletions (user_id, requested, scheduled) VALUES ($1, $2, $3) ON CONFLICT (user_id) DO NOTHING",
+		u.as_str(),
+		Utc::now(),
+		scheduled
+	)
+	.execute(db)
+	.await?;
+
+	Ok(sqlx::query_as!(
+		AccountDeletion,
+		"SELECT requested, scheduled FROM account_deletions WHERE user_id = $1",
+		u.as_str()
+	)
+	.fetch_one(db)
+	.await?)
+}
+
+pub async fn get_deletion(db: &PgPool, u: &UserId) -> anyhow::Result<Option<AccountDeletion>> {
+	Ok(sqlx::query_as!(
+		AccountDeletion,
+		"SELECT requested, scheduled FROM account_deletions WHERE user_id = $1",
+		u.as_str()
+	)
+	.fetch_optional(db)
+	.await?)
+}
+
+pub async fn cancel_deletion(db: &PgPool, u: &UserId) -> anyhow::Result<u64> {
+	let res = sqlx::query!(
+		"DELETE FROM account_deletions WHERE user_id = $1",
+		u.as_str()
+	)
+	.execute(db)
+	.await?
+	.rows_affected();
+
+	Ok(res)
+}
+
+pub async fn get_due_deletions(db: &PgPool) -> anyhow::Result<Vec<String>> {
+	Ok(
+		sqlx::query_scalar!("SELECT user_id FROM account_deletions WHERE scheduled < NOW()")
+			.fetch_all(db)
+			.await?,
+	)
+}
+
+// Removes every row belonging to the user.
+pub async fn delete_account(db: &PgPool, user_id: &str) -> anyhow::Result<()> {
+	let mut tx = db.begin().await?;
+
+	sqlx::query!("DELETE FROM entries WHERE user_id = $1", user_id)
+		.execute(&mut *tx)
+		.await?;
+
+	sqlx::query!("DELETE FROM timeslots WHERE user_id = $1", user_id)
+		.execute(&mut *tx)
+		.await?;
+
+	sqlx::query!("DELETE FROM sessions WHERE user_id = $1", user_id)
+		.execute(&mut *tx)
+		.await?;
+
+	sqlx::query!("DELETE FROM feed_tokens WHERE user_id = $1", user_id)
+		.execute(&mut *tx)
+		.await?;
+
+	// The log contains student names, so it can't outlive the account.
+	sqlx::query!("DELETE FROM audit_log WHERE user_id = $1", user_id)
+		.execute(&mut *tx)
+		.await?;
+
+	sqlx::query!("DELETE FROM account_deletions WHERE user_id = $1", user_id)
+		.execute(&mut *tx)
+		.await?;
+
+	tx.commit().await?;
+
+	Ok(())
+}
@@ -0,0 +1,179 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{prelude::Type, PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::auth::UserId;
+use crate::db::crypto;
+use crate::db::model::{WebEntry, WebTimeSlot};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Type)]
+#[sqlx(type_name = "audit_action")]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+	Create,
+	Update,
+	Delete,
+	Restore,
+}
+
+// Who made a change and in which request.
+pub struct AuditContext {
+	pub user_id: String,
+	pub request_id: Option<String>,
+}
+
+impl AuditContext {
+	pub fn new(u: &UserId, request_id: &impl ToString) -> AuditContext {
+		AuditContext {
+			user_id: u.as_str().to_owned(),
+			request_id: Some(request_id.to_string()),
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct AuditLogEntry {
+	pub id: i64,
+	pub request_id: Option<String>,
+	pub timeslot_id: Uuid,
+	pub index: Option<i32>,
+	pub action: AuditAction,
+	pub before: Option<Value>,
+	pub after: Option<Value>,
+	pub created: DateTime<Utc>,
+}
+
+// Student names in snapshots are encrypted just like in the other tables.
+// Snapshots aren't re-encrypted by `encrypt_existing`, since the log is append-only,
+// so old keys have to stay configured as long as they are used in the log.
+fn map_students(snapshot: &mut Value, f: fn(&str) -> anyhow::Result<String>) -> anyhow::Result<()> {
+	let Some(Value::Array(students)) = snapshot.get_mut("students") else {
+		return Ok(());
+	};
+
+	for s in students {
+		// Timeslots contain plain names, entries contain `StudentState`s.
+		let name = match s {
+			Value::String(n) => n,
+			Value::Object(o) => match o.get_mut("student") {
+				Some(Value::String(n)) => n,
+				_ => continue,
+			},
+			_ => continue,
+		};
+
+		*name = f(name)?;
+	}
+
+	Ok(())
+}
+
+fn snapshot(v: Option<&impl Serialize>) -> anyhow::Result<Option<Value>> {
+	v.map(|v| {
+		let mut snapshot = serde_json::to_value(v)?;
+		map_students(&mut snapshot, crypto::encrypt)?;
+		Ok(snapshot)
+	})
+	.transpose()
+}
+
+async fn insert(
+	db: impl PgExecutor<'_>,
+	ctx: &AuditContext,
+	action: AuditAction,
+	timeslot_id: Uuid,
+	index: Option<i32>,
+	before: Option<Value>,
+	after: Option<Value>,
+) -> anyhow::Result<()> {
+	sqlx::query!(
+		"INSERT INTO audit_log (user_id, request_id, timeslot_id, index, action, before, after) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+		ctx.user_id,
+		ctx.request_id,
+		timeslot_id,
+		index,
+		action as AuditAction,
+		before,
+		after
+	)
+	.execute(db)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn log_timeslot(
+	db: impl PgExecutor<'_>,
+	ctx: &AuditContext,
+	action: AuditAction,
+	before: Option<&WebTimeSlot>,
+	after: Option<&WebTimeSlot>,
+) -> anyhow::Result<()> {
+	let id = before
+		.or(after)
+		.ok_or_else(|| anyhow::anyhow!("audit record without timeslot"))?
+		.id;
+
+	insert(
+		db,
+		ctx,
+		action,
+		id,
+		None,
+		snapshot(before)?,
+		snapshot(after)?,
+	)
+	.await
+}
+
+pub async fn log_entry(
+	db: impl PgExecutor<'_>,
+	ctx: &AuditContext,
+	action: AuditAction,
+	before: Option<&WebEntry>,
+	after: Option<&WebEntry>,
+) -> anyhow::Result<()> {
+	let entry = before
+		.or(after)
+		.ok_or_else(|| anyhow::anyhow!("audit record without entry"))?;
+
+	insert(
+		db,
+		ctx,
+		action,
+		entry.timeslot_id,
+		Some(entry.index.try_into()?),
+		snapshot(before)?,
+		snapshot(after)?,
+	)
+	.await
+}
+
+// Without an index, the records of the timeslot and all of its entries are returned.
+pub async fn get_audit_log(
+	db: &PgPool,
+	u: &UserId,
+	timeslot_id: Uuid,
+	index: Option<i32>,
+) -> anyhow::Result<Vec<AuditLogEntry>> {
+	let mut records = sqlx::query_as!(
+		AuditLogEntry,
+		r#"SELECT id, request_id, timeslot_id, index, action AS "action: AuditAction", before, after, created FROM audit_log WHERE user_id = $1 AND timeslot_id = $2 AND ($3::integer IS NULL OR index = $3) ORDER BY id"#,
+		u.as_str(),
+		timeslot_id,
+		index
+	)
+	.fetch_all(db)
+	.await?;
+
+	for r in &mut records {
+		for snapshot in [&mut r.before, &mut r.after].into_iter().flatten() {
+			map_students(snapshot, crypto::decrypt)?;
+		}
+	}
+
+	Ok(records)
+}
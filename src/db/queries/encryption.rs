@@ -0,0 +1,80 @@
+use anyhow::Context;
+use itertools::Itertools;
+use sqlx::PgPool;
+
+use crate::db::{crypto, model::StudentState};
+
+// Encrypts plaintext student names and re-encrypts names using an old key.
+// Returns the number of updated timeslots and entries.
+pub async fn encrypt_existing(db: &PgPool) -> anyhow::Result<(u64, u64)> {
+	let cipher = crypto::cipher().context("encryption isn't configured")?;
+
+	let mut tx = db.begin().await?;
+
+	let timeslots = sqlx::query!("SELECT id, students FROM timeslots FOR UPDATE")
+		.fetch_all(&mut *tx)
+		.await?;
+
+	let mut updated_timeslots = 0;
+
+	for ts in timeslots {
+		if ts.students.iter().all(|s| cipher.is_current(s)) {
+			continue;
+		}
+
+		let students: Vec<String> = ts
+			.students
+			.iter()
+			.map(|s| cipher.encrypt(&cipher.decrypt(s)?))
+			.try_collect()?;
+
+		sqlx::query!(
+			"UPDATE timeslots SET students = $2 WHERE id = $1",
+			ts.id,
+			&students[..]
+		)
+		.execute(&mut *tx)
+		.await?;
+
+		updated_timeslots += 1;
+	}
+
+	let entries = sqlx::query!(
+		r#"SELECT id, students AS "students: Vec<StudentState>" FROM entries FOR UPDATE"#
+	)
+	.fetch_all(&mut *tx)
+	.await?;
+
+	let mut updated_entries = 0;
+
+	for e in entries {
+		if e.students.iter().all(|s| cipher.is_current(&s.student)) {
+			continue;
+		}
+
+		let students: Vec<StudentState> = e
+			.students
+			.iter()
+			.map(|s| {
+				Ok(StudentState {
+					student: cipher.encrypt(&cipher.decrypt(&s.student)?)?,
+					status: s.status,
+				})
+			})
+			.collect::<anyhow::Result<_>>()?;
+
+		sqlx::query!(
+			"UPDATE entries SET students = $2 WHERE id = $1",
+			e.id,
+			students as Vec<StudentState>
+		)
+		.execute(&mut *tx)
+		.await?;
+
+		updated_entries += 1;
+	}
+
+	tx.commit().await?;
+
+	Ok((updated_timeslots, updated_entries))
+}
@@ -1,18 +1,20 @@
 use std::ops::Range;
 
-use sqlx::PgPool;
+use anyhow::Context;
+use sqlx::{PgExecutor, PgPool};
 use tracing::error;
 use uuid::Uuid;
 
 use crate::auth::UserId;
+use crate::db::crypto;
 use crate::db::model::{self, Entry, EntryState, StudentState, WebEntry};
 
 pub async fn get_entries_by_timeslot_id(
-	db: &PgPool,
+	db: impl PgExecutor<'_>,
 	u: &UserId,
 	id: uuid::Uuid,
 ) -> anyhow::Result<Vec<WebEntry>> {
-	let entries_db = sqlx::query_as!(Entry, r#"SELECT user_id, index, timeslot_id, state_enum AS "state_enum: EntryState", students AS "students: Vec<StudentState>" FROM entries WHERE timeslot_id = $1 AND user_id = $2"#, id, u.as_str())
+	let entries_db = sqlx::query_as!(Entry, r#"SELECT user_id, index, timeslot_id, state_enum AS "state_enum: EntryState", students AS "students: Vec<StudentState>" FROM entries WHERE timeslot_id = $1 AND user_id = $2 AND deleted_at IS NULL"#, id, u.as_str())
 		.fetch_all(db)
 		.await?;
 
@@ -24,13 +26,12 @@ pub async fn get_entries_by_timeslot_id(
 	Ok(entries)
 }
 
-pub async fn get_entries_with_index_in(
-	db: &PgPool,
+// All entries of the user, e.g. to render every timeslot at once.
+pub async fn get_entries_by_user(
+	db: impl PgExecutor<'_>,
 	u: &UserId,
-	timeslot_id: Uuid,
-	indexes: Vec<i32>,
 ) -> anyhow::Result<Vec<WebEntry>> {
-	let entries_db = sqlx::query_as!(Entry, r#"SELECT user_id, index, timeslot_id, state_enum AS "state_enum: EntryState", students AS "students: Vec<StudentState>" FROM entries WHERE timeslot_id = $1 AND user_id = $2 AND index = ANY($3)"#, timeslot_id, u.as_str(), &indexes[..])
+	let entries_db = sqlx::query_as!(Entry, r#"SELECT user_id, index, timeslot_id, state_enum AS "state_enum: EntryState", students AS "students: Vec<StudentState>" FROM entries WHERE user_id = $1 AND deleted_at IS NULL"#, u.as_str())
 		.fetch_all(db)
 		.await?;
 
@@ -42,6 +43,61 @@ pub async fn get_entries_with_index_in(
 	Ok(entries)
 }
 
+// Entries ordered by descending index, starting below `before`.
+// Also returns the cursor of the next page, if there are more entries.
+pub async fn get_entries_page(
+	db: impl PgExecutor<'_>,
+	u: &UserId,
+	id: uuid::Uuid,
+	before: Option<i32>,
+	limit: Option<u32>,
+) -> anyhow::Result<(Vec<WebEntry>, Option<u32>)> {
+	// One more entry than requested is fetched, to know whether there is a next page.
+	let fetch_limit = limit.map(|l| i64::from(l) + 1);
+
+	let mut entries_db = sqlx::query_as!(Entry, r#"SELECT user_id, index, timeslot_id, state_enum AS "state_enum: EntryState", students AS "students: Vec<StudentState>" FROM entries WHERE timeslot_id = $1 AND user_id = $2 AND deleted_at IS NULL AND ($3::integer IS NULL OR index < $3) ORDER BY index DESC LIMIT $4"#, id, u.as_str(), before, fetch_limit)
+		.fetch_all(db)
+		.await?;
+
+	let mut next = None;
+
+	if let Some(limit) = limit {
+		let limit = usize::try_from(limit)?;
+
+		if entries_db.len() > limit {
+			entries_db.truncate(limit);
+			next = entries_db
+				.last()
+				.map(|e| u32::try_from(e.index))
+				.transpose()?;
+		}
+	}
+
+	let entries: Vec<WebEntry> = entries_db
+		.into_iter()
+		.filter_map(model::convert_entry)
+		.collect();
+
+	Ok((entries, next))
+}
+
+// Returns the given occurrences, which don't have an entry.
+pub async fn get_missing_occurrences(
+	db: &PgPool,
+	u: &UserId,
+	timeslot_ids: &[Uuid],
+	indexes: &[i32],
+) -> anyhow::Result<Vec<(Uuid, i32)>> {
+	let missing = sqlx::query!(r#"SELECT o.timeslot_id AS "timeslot_id!", o.index AS "index!" FROM UNNEST($2::uuid[], $3::integer[]) AS o(timeslot_id, index) WHERE NOT EXISTS (SELECT 1 FROM entries e WHERE e.user_id = $1 AND e.timeslot_id = o.timeslot_id AND e.index = o.index AND e.deleted_at IS NULL)"#, u.as_str(), timeslot_ids, indexes)
+		.fetch_all(db)
+		.await?
+		.into_iter()
+		.map(|r| (r.timeslot_id, r.index))
+		.collect();
+
+	Ok(missing)
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum InsertEntryError {
 	#[error("duplicate index")]
@@ -50,9 +106,20 @@ pub enum InsertEntryError {
 	Other(#[from] anyhow::Error),
 }
 
-pub async fn insert_entry(db: &PgPool, entry: Entry) -> Result<(), InsertEntryError> {
+pub async fn insert_entry(db: impl PgExecutor<'_>, entry: Entry) -> Result<(), InsertEntryError> {
 	let index: i32 = entry.index;
 
+	let students = entry
+		.students
+		.into_iter()
+		.map(|s| {
+			Ok(StudentState {
+				student: crypto::encrypt(&s.student)?,
+				status: s.status,
+			})
+		})
+		.collect::<anyhow::Result<Vec<_>>>()?;
+
 	match sqlx::query!(
 		"INSERT INTO entries (id, user_id, index, timeslot_id, state_enum, students) VALUES ($1, $2, $3, $4, $5, $6)",
 		uuid::Uuid::new_v4(),
@@ -60,7 +127,7 @@ pub async fn insert_entry(db: &PgPool, entry: Entry) -> Result<(), InsertEntryEr
 		index,
 		entry.timeslot_id,
 		entry.state_enum as EntryState,
-		entry.students as Vec<StudentState>
+		students as Vec<StudentState>
 	)
 	.execute(db)
 	.await
@@ -88,7 +155,7 @@ pub async fn get_entry_by_index_range(
 	id: uuid::Uuid,
 	index_range: Range<i32>,
 ) -> anyhow::Result<Vec<WebEntry>> {
-	let entries_db = sqlx::query_as!(Entry, r#"SELECT user_id, timeslot_id, index, state_enum AS "state_enum: EntryState", students AS "students: Vec<StudentState>" FROM entries WHERE user_id = $1 AND timeslot_id = $2 AND index >= $3 AND index <= $4"#, u.as_str(), id, index_range.start, index_range.end)
+	let entries_db = sqlx::query_as!(Entry, r#"SELECT user_id, timeslot_id, index, state_enum AS "state_enum: EntryState", students AS "students: Vec<StudentState>" FROM entries WHERE user_id = $1 AND timeslot_id = $2 AND index >= $3 AND index <= $4 AND deleted_at IS NULL"#, u.as_str(), id, index_range.start, index_range.end)
 		.fetch_all(db)
 		.await?;
 
@@ -100,19 +167,200 @@ pub async fn get_entry_by_index_range(
 	Ok(entries)
 }
 
-pub async fn delete_entry_by_id(
+// Fetches the entries of many occurrences at once, `timeslot_ids` and `indexes` are pairs.
+pub async fn get_entries_for_occurrences(
 	db: &PgPool,
 	u: &UserId,
+	timeslot_ids: &[Uuid],
+	indexes: &[i32],
+) -> anyhow::Result<Vec<WebEntry>> {
+	let entries_db = sqlx::query_as!(Entry, r#"SELECT e.user_id, e.index, e.timeslot_id, e.state_enum AS "state_enum: EntryState", e.students AS "students: Vec<StudentState>" FROM entries e JOIN UNNEST($2::uuid[], $3::integer[]) AS o(timeslot_id, index) ON e.timeslot_id = o.timeslot_id AND e.index = o.index WHERE e.user_id = $1 AND e.deleted_at IS NULL"#, u.as_str(), timeslot_ids, indexes)
+		.fetch_all(db)
+		.await?;
+
+	let entries: Vec<WebEntry> = entries_db
+		.into_iter()
+		.filter_map(model::convert_entry)
+		.collect();
+
+	Ok(entries)
+}
+
+// Moves the entry to the trash.
+// Returns the deleted entry.
+pub async fn delete_entry_by_id(
+	db: impl PgExecutor<'_>,
+	u: &UserId,
 	ts_id: uuid::Uuid,
 	index: i32,
-) -> anyhow::Result<()> {
-	sqlx::query!(
-		"DELETE FROM entries where user_id = $1 AND timeslot_id = $2 AND index = $3",
-		u.as_str(),
-		ts_id,
-		index
-	)
-	.execute(db)
-	.await?;
-	Ok(())
+) -> anyhow::Result<Option<WebEntry>> {
+	let Some(entry) = sqlx::query_as!(Entry, r#"UPDATE entries SET deleted_at = now() WHERE user_id = $1 AND timeslot_id = $2 AND index = $3 AND deleted_at IS NULL RETURNING user_id, index, timeslot_id, state_enum AS "state_enum: EntryState", students AS "students: Vec<StudentState>""#, u.as_str(), ts_id, index)
+		.fetch_optional(db)
+		.await? else {
+		return Ok(None);
+	};
+
+	Ok(Some(
+		model::convert_entry(entry).context("invalid data in db")?,
+	))
+}
+
+// Restores the most recently deleted entry with this index.
+// The timeslot of the entry isn't checked, so it has to exist.
+pub async fn restore_entry_by_id(
+	db: impl PgExecutor<'_>,
+	u: &UserId,
+	ts_id: uuid::Uuid,
+	index: i32,
+) -> Result<Option<WebEntry>, InsertEntryError> {
+	let entry = match sqlx::query_as!(Entry, r#"UPDATE entries SET deleted_at = NULL WHERE id = (SELECT id FROM entries WHERE user_id = $1 AND timeslot_id = $2 AND index = $3 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT 1) RETURNING user_id, index, timeslot_id, state_enum AS "state_enum: EntryState", students AS "students: Vec<StudentState>""#, u.as_str(), ts_id, index)
+		.fetch_optional(db)
+		.await
+	{
+		Ok(Some(e)) => e,
+		Ok(None) => return Ok(None),
+		Err(sqlx::Error::Database(d)) if d.kind() == sqlx::error::ErrorKind::UniqueViolation => {
+			return Err(InsertEntryError::Duplicate)
+		}
+		Err(e) => {
+			let res: anyhow::Error = e.into();
+			Err(res)?
+		}
+	};
+
+	Ok(Some(
+		model::convert_entry(entry).context("invalid data in db")?,
+	))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+	use chrono::{NaiveDate, NaiveTime};
+	use sqlx::PgPool;
+	use uuid::Uuid;
+
+	use crate::auth::UserId;
+	use crate::db::model::{DbTime, DbTimerange, Entry, EntryState, TimeSlot};
+	use crate::db::queries::timeslot::insert_timeslot;
+
+	use super::{delete_entry_by_id, get_entries_page, get_missing_occurrences, insert_entry};
+
+	// Weekly on mondays from 2024-01-01 until 2024-03-25, so indices 0 to 12.
+	pub(crate) async fn insert_test_timeslot(db: &PgPool, u: &UserId) -> Uuid {
+		let id = Uuid::new_v4();
+
+		insert_timeslot(
+			db,
+			TimeSlot {
+				user_id: u.as_str().to_owned(),
+				id,
+				subject: "math".into(),
+				students: Vec::new(),
+				time: DbTime {
+					beginning: NaiveTime::from_hms_opt(14, 0, 0).unwrap(),
+					finish: NaiveTime::from_hms_opt(15, 0, 0).unwrap(),
+				},
+				timerange: DbTimerange {
+					beginning: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+					finish: NaiveDate::from_ymd_opt(2024, 3, 25).unwrap(),
+				},
+				timezone: "Europe/Berlin".into(),
+				hourly_rate: None,
+				archived: None,
+			},
+		)
+		.await
+		.unwrap();
+
+		id
+	}
+
+	pub(crate) async fn insert_test_entry(db: &PgPool, u: &UserId, timeslot_id: Uuid, index: i32) {
+		insert_entry(
+			db,
+			Entry {
+				user_id: u.as_str().to_owned(),
+				index,
+				timeslot_id,
+				state_enum: EntryState::Holidays,
+				students: Vec::new(),
+			},
+		)
+		.await
+		.unwrap();
+	}
+
+	#[sqlx::test]
+	async fn test_get_entries_page(db: PgPool) {
+		let u = UserId::from_db("hello".into());
+		let ts = insert_test_timeslot(&db, &u).await;
+
+		for index in 0..5 {
+			insert_test_entry(&db, &u, ts, index).await;
+		}
+
+		// A deleted entry with the same timeslot and index as the last entry of the second page.
+		delete_entry_by_id(&db, &u, ts, 1).await.unwrap().unwrap();
+		insert_test_entry(&db, &u, ts, 1).await;
+
+		let mut pages = Vec::new();
+		let mut before = None;
+
+		loop {
+			let (entries, next) = get_entries_page(&db, &u, ts, before, Some(2))
+				.await
+				.unwrap();
+
+			pages.push(entries.iter().map(|e| e.index).collect::<Vec<_>>());
+
+			let Some(next) = next else {
+				break;
+			};
+
+			before = Some(i32::try_from(next).unwrap());
+		}
+
+		assert_eq!(pages, vec![vec![4, 3], vec![2, 1], vec![0]]);
+
+		// Exactly one full page doesn't have a next page.
+		let (entries, next) = get_entries_page(&db, &u, ts, None, Some(5)).await.unwrap();
+		assert_eq!(entries.len(), 5);
+		assert_eq!(next, None);
+
+		// Without a limit every entry is returned.
+		let (entries, next) = get_entries_page(&db, &u, ts, Some(3), None).await.unwrap();
+		assert_eq!(
+			entries.iter().map(|e| e.index).collect::<Vec<_>>(),
+			vec![2, 1, 0]
+		);
+		assert_eq!(next, None);
+	}
+
+	#[sqlx::test]
+	async fn test_get_missing_occurrences(db: PgPool) {
+		let u = UserId::from_db("hello".into());
+		let ts = insert_test_timeslot(&db, &u).await;
+
+		for index in 0..3 {
+			insert_test_entry(&db, &u, ts, index).await;
+		}
+
+		// Deleted entries are missing again.
+		delete_entry_by_id(&db, &u, ts, 1).await.unwrap().unwrap();
+
+		let mut missing = get_missing_occurrences(&db, &u, &[ts; 4], &[0, 1, 2, 3])
+			.await
+			.unwrap();
+		missing.sort_unstable();
+
+		assert_eq!(missing, vec![(ts, 1), (ts, 3)]);
+
+		// Entries of other users don't count.
+		let other = UserId::from_db("other".into());
+		let missing = get_missing_occurrences(&db, &other, &[ts], &[0])
+			.await
+			.unwrap();
+
+		assert_eq!(missing, vec![(ts, 0)]);
+	}
 }
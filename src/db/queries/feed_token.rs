@@ -0,0 +1,70 @@
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::auth::UserId;
+
+#[derive(thiserror::Error, Debug)]
+pub enum InsertFeedTokenError {
+	#[error("user already has a feed token")]
+	Duplicate,
+	#[error("internal server error")]
+	Other(#[from] anyhow::Error),
+}
+
+pub async fn insert(
+	db: &PgPool,
+	u: &UserId,
+	token_hash: &[u8],
+) -> Result<(), InsertFeedTokenError> {
+	match sqlx::query!(
+		"INSERT INTO feed_tokens (id, user_id, token_hash, created) VALUES ($1, $2, $3, $4)",
+		Uuid::new_v4(),
+		u.as_str(),
+		token_hash,
+		Utc::now()
+	)
+	.execute(db)
+	.await
+	{
+		Ok(_) => Ok(()),
+		Err(sqlx::Error::Database(d))
+			if matches!(d.kind(), sqlx::error::ErrorKind::UniqueViolation) =>
+		{
+			Err(InsertFeedTokenError::Duplicate)
+		}
+		Err(e) => Err(InsertFeedTokenError::Other(e.into())),
+	}
+}
+
+pub async fn replace(db: &PgPool, u: &UserId, token_hash: &[u8]) -> anyhow::Result<u64> {
+	let res = sqlx::query!(
+		"UPDATE feed_tokens SET token_hash = $2, created = $3 WHERE user_id = $1",
+		u.as_str(),
+		token_hash,
+		Utc::now()
+	)
+	.execute(db)
+	.await?
+	.rows_affected();
+
+	Ok(res)
+}
+
+pub async fn delete(db: &PgPool, u: &UserId) -> anyhow::Result<u64> {
+	let res = sqlx::query!("DELETE FROM feed_tokens WHERE user_id = $1", u.as_str())
+		.execute(db)
+		.await?
+		.rows_affected();
+
+	Ok(res)
+}
+
+pub async fn get_user_id(db: &PgPool, token_hash: &[u8]) -> anyhow::Result<Option<String>> {
+	Ok(sqlx::query_scalar!(
+		"SELECT user_id FROM feed_tokens WHERE token_hash = $1",
+		token_hash
+	)
+	.fetch_optional(db)
+	.await?)
+}
@@ -1,3 +1,9 @@
+pub mod account;
+pub mod audit;
+pub mod encryption;
 pub mod entry;
+pub mod feed_token;
+pub mod retention;
 pub mod session;
 pub mod timeslot;
+pub mod trash;
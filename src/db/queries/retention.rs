@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use itertools::Itertools;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::crypto;
+use crate::db::model::StudentState;
+
+// Returns the number of deleted timeslots and entries.
+pub async fn delete_expired(db: &PgPool, cutoff: NaiveDate) -> anyhow::Result<(u64, u64)> {
+	let mut tx = db.begin().await?;
+
+	sqlx::query!(
+		"DELETE FROM audit_log WHERE timeslot_id IN (SELECT id FROM timeslots WHERE (timerange).finish < $1)",
+		cutoff
+	)
+	.execute(&mut *tx)
+	.await?;
+
+	let entries = sqlx::query!(
+		"DELETE FROM entries WHERE timeslot_id IN (SELECT id FROM timeslots WHERE (timerange).finish < $1)",
+		cutoff
+	)
+	.execute(&mut *tx)
+	.await?
+	.rows_affected();
+
+	let timeslots = sqlx::query!(
+		"DELETE FROM timeslots WHERE (timerange).finish < $1",
+		cutoff
+	)
+	.execute(&mut *tx)
+	.await?
+	.rows_affected();
+
+	tx.commit().await?;
+
+	Ok((timeslots, entries))
+}
+
+pub async fn get_expired_timeslots(db: &PgPool, cutoff: NaiveDate) -> anyhow::Result<Vec<Uuid>> {
+	Ok(sqlx::query_scalar!(
+		"SELECT id FROM timeslots WHERE (timerange).finish < $1 AND NOT anonymised",
+		cutoff
+	)
+	.fetch_all(db)
+	.await?)
+}
+
+// Replaces student names with numbered placeholders.
+// Returns the number of anonymised entries.
+pub async fn anonymise_timeslot(db: &PgPool, id: Uuid) -> anyhow::Result<u64> {
+	let mut tx = db.begin().await?;
+
+	let original: Vec<String> = sqlx::query_scalar!(
+		"SELECT students FROM timeslots WHERE id = $1 FOR UPDATE",
+		id
+	)
+	.fetch_one(&mut *tx)
+	.await?
+	.iter()
+	.map(|s| crypto::decrypt(s))
+	.try_collect()?;
+
+	let students: Vec<String> = (1..=original.len())
+		.map(|i| format!("student {i}"))
+		.collect();
+
+	let names: HashMap<&str, &str> = original
+		.iter()
+		.map(String::as_str)
+		.zip(students.iter().map(String::as_str))
+		.collect();
+
+	// Entries shouldn't contain students, which aren't part of the timeslot.
+	let rename = |name: &str| {
+		crypto::encrypt(
+			names
+				.get(crypto::decrypt(name)?.as_str())
+				.copied()
+				.unwrap_or("unknown student"),
+		)
+	};
+
+	let encrypted: Vec<String> = students.iter().map(|s| crypto::encrypt(s)).try_collect()?;
+
+	// The snapshots in the log contain the original names.
+	sqlx::query!("DELETE FROM audit_log WHERE timeslot_id = $1", id)
+		.execute(&mut *tx)
+		.await?;
+
+	sqlx::query!(
+		"UPDATE timeslots SET students = $2, anonymised = true WHERE id = $1",
+		id,
+		&encrypted[..]
+	)
+	.execute(&mut *tx)
+	.await?;
+
+	let entries = sqlx::query!(
+		r#"SELECT id, students AS "students: Vec<StudentState>" FROM entries WHERE timeslot_id = $1"#,
+		id
+	)
+	.fetch_all(&mut *tx)
+	.await?;
+
+	for e in &entries {
+		let students: Vec<StudentState> = e
+			.students
+			.iter()
+			.map(|s| {
+				Ok(StudentState {
+					student: rename(&s.student)?,
+					status: s.status,
+				})
+			})
+			.collect::<anyhow::Result<_>>()?;
+
+		sqlx::query!(
+			"UPDATE entries SET students = $2 WHERE id = $1",
+			e.id,
+			students as Vec<StudentState>
+		)
+		.execute(&mut *tx)
+		.await?;
+	}
+
+	tx.commit().await?;
+
+	Ok(entries.len().try_into()?)
+}
@@ -1,14 +1,26 @@
 use anyhow::Context;
-use sqlx::PgPool;
+use chrono::{NaiveDate, Utc};
+use itertools::Itertools;
+use sqlx::{PgConnection, PgExecutor, PgPool};
 use uuid::Uuid;
 
 use crate::{
 	auth::UserId,
-	db::model::{self, DbTime, DbTimerange, TimeSlot, WebTimeSlot},
+	db::{
+		crypto,
+		model::{
+			self, DbTime, DbTimerange, Entry, EntryState, StudentState, TimeSlot, WebEntry,
+			WebTimeSlot,
+		},
+	},
 };
 
-pub async fn get_timeslots(db: &PgPool, u: &UserId) -> anyhow::Result<Vec<WebTimeSlot>> {
-	let timeslots_db: Vec<TimeSlot> = sqlx::query_as!(TimeSlot, r#"SELECT id, user_id, subject, students, time AS "time: DbTime", timerange AS "timerange: DbTimerange", timezone FROM timeslots WHERE user_id = $1"#, u.as_str())
+pub async fn get_timeslots(
+	db: &PgPool,
+	u: &UserId,
+	include_archived: bool,
+) -> anyhow::Result<Vec<WebTimeSlot>> {
+	let timeslots_db: Vec<TimeSlot> = sqlx::query_as!(TimeSlot, r#"SELECT id, user_id, subject, students, time AS "time: DbTime", timerange AS "timerange: DbTimerange", timezone, hourly_rate, archived FROM timeslots WHERE user_id = $1 AND deleted_at IS NULL AND ($2 OR NOT COALESCE(archived, false))"#, u.as_str(), include_archived)
 		.fetch_all(db)
 		.await?;
 
@@ -21,11 +33,11 @@ pub async fn get_timeslots(db: &PgPool, u: &UserId) -> anyhow::Result<Vec<WebTim
 }
 
 pub async fn get_timeslot_by_id(
-	db: &PgPool,
+	db: impl PgExecutor<'_>,
 	u: &UserId,
 	id: Uuid,
 ) -> anyhow::Result<Option<WebTimeSlot>> {
-	let timeslot_db: TimeSlot = match sqlx::query_as!(TimeSlot, r#"SELECT user_id, id, subject, students, time AS "time: DbTime", timerange AS "timerange: DbTimerange", timezone FROM timeslots WHERE user_id = $1 AND id = $2"#, u.as_str(), id)
+	let timeslot_db: TimeSlot = match sqlx::query_as!(TimeSlot, r#"SELECT user_id, id, subject, students, time AS "time: DbTime", timerange AS "timerange: DbTimerange", timezone, hourly_rate, archived FROM timeslots WHERE user_id = $1 AND id = $2 AND deleted_at IS NULL"#, u.as_str(), id)
 		.fetch_optional(db)
 		.await {
 			Ok(ts_opt) => if let Some(ts) = ts_opt { ts } else { return Ok(None) },
@@ -37,22 +49,117 @@ pub async fn get_timeslot_by_id(
 	))
 }
 
-pub async fn insert_timeslot(db: &PgPool, ts: TimeSlot) -> anyhow::Result<()> {
-	sqlx::query!("INSERT INTO timeslots (id, user_id, subject, students, time, timerange, timezone) VALUES ($1, $2, $3, $4, $5, $6, $7)", ts.id, ts.user_id, ts.subject, ts.students as Vec<String>, ts.time as DbTime, ts.timerange as DbTimerange, ts.timezone)
+pub async fn insert_timeslot(db: impl PgExecutor<'_>, ts: TimeSlot) -> anyhow::Result<()> {
+	let students: Vec<String> = ts
+		.students
+		.iter()
+		.map(|s| crypto::encrypt(s))
+		.try_collect()?;
+
+	sqlx::query!("INSERT INTO timeslots (id, user_id, subject, students, time, timerange, timezone, hourly_rate) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)", ts.id, ts.user_id, ts.subject, students as Vec<String>, ts.time as DbTime, ts.timerange as DbTimerange, ts.timezone, ts.hourly_rate)
 		.execute(db)
 		.await?;
 	Ok(())
 }
 
-pub async fn delete_timeslot_by_id(db: &PgPool, u: &UserId, id: Uuid) -> anyhow::Result<u64> {
-	let res = sqlx::query!(
-		"DELETE FROM timeslots WHERE user_id = $1 AND id = $2",
+// Moves the timeslot to the trash, its entries are moved along with it.
+// Returns the deleted timeslot.
+pub async fn delete_timeslot_by_id(
+	db: &mut PgConnection,
+	u: &UserId,
+	id: Uuid,
+) -> anyhow::Result<Option<WebTimeSlot>> {
+	let deleted_at = Utc::now();
+
+	let Some(ts) = sqlx::query_as!(TimeSlot, r#"UPDATE timeslots SET deleted_at = $3 WHERE user_id = $1 AND id = $2 AND deleted_at IS NULL RETURNING user_id, id, subject, students, time AS "time: DbTime", timerange AS "timerange: DbTimerange", timezone, hourly_rate, archived"#, u.as_str(), id, deleted_at)
+		.fetch_optional(&mut *db)
+		.await? else {
+		return Ok(None);
+	};
+
+	// Using the same timestamp, so restoring the timeslot only restores these entries.
+	sqlx::query!(
+		"UPDATE entries SET deleted_at = $3 WHERE user_id = $1 AND timeslot_id = $2 AND deleted_at IS NULL",
 		u.as_str(),
-		id
+		id,
+		deleted_at
 	)
-	.execute(db)
-	.await?
-	.rows_affected();
+	.execute(&mut *db)
+	.await?;
+
+	Ok(Some(model::convert_ts(ts).context("invalid data in db")?))
+}
+
+// Restores a deleted timeslot and the entries, which were deleted along with it.
+pub async fn restore_timeslot_by_id(
+	db: &mut PgConnection,
+	u: &UserId,
+	id: Uuid,
+) -> anyhow::Result<Option<(WebTimeSlot, Vec<WebEntry>)>> {
+	let entries = sqlx::query_as!(Entry, r#"UPDATE entries SET deleted_at = NULL WHERE user_id = $1 AND timeslot_id = $2 AND deleted_at = (SELECT deleted_at FROM timeslots WHERE user_id = $1 AND id = $2) RETURNING user_id, index, timeslot_id, state_enum AS "state_enum: EntryState", students AS "students: Vec<StudentState>""#, u.as_str(), id)
+		.fetch_all(&mut *db)
+		.await?;
+
+	let Some(ts) = sqlx::query_as!(TimeSlot, r#"UPDATE timeslots SET deleted_at = NULL WHERE user_id = $1 AND id = $2 AND deleted_at IS NOT NULL RETURNING user_id, id, subject, students, time AS "time: DbTime", timerange AS "timerange: DbTimerange", timezone, hourly_rate, archived"#, u.as_str(), id)
+		.fetch_optional(&mut *db)
+		.await? else {
+		return Ok(None);
+	};
+
+	let entries = entries
+		.into_iter()
+		.map(|e| model::convert_entry(e).context("invalid data in db"))
+		.try_collect()?;
+
+	Ok(Some((
+		model::convert_ts(ts).context("invalid data in db")?,
+		entries,
+	)))
+}
 
-	Ok(res)
+// Returns the updated timeslot.
+pub async fn update_timeslot_hourly_rate(
+	db: impl PgExecutor<'_>,
+	u: &UserId,
+	id: Uuid,
+	hourly_rate: Option<i32>,
+) -> anyhow::Result<Option<WebTimeSlot>> {
+	let Some(ts) = sqlx::query_as!(TimeSlot, r#"UPDATE timeslots SET hourly_rate = $3 WHERE user_id = $1 AND id = $2 AND deleted_at IS NULL RETURNING user_id, id, subject, students, time AS "time: DbTime", timerange AS "timerange: DbTimerange", timezone, hourly_rate, archived"#, u.as_str(), id, hourly_rate)
+		.fetch_optional(db)
+		.await? else {
+		return Ok(None);
+	};
+
+	Ok(Some(model::convert_ts(ts).context("invalid data in db")?))
+}
+
+// Returns the updated timeslot.
+pub async fn set_timeslot_archived(
+	db: impl PgExecutor<'_>,
+	u: &UserId,
+	id: Uuid,
+	archived: bool,
+) -> anyhow::Result<Option<WebTimeSlot>> {
+	let Some(ts) = sqlx::query_as!(TimeSlot, r#"UPDATE timeslots SET archived = $3 WHERE user_id = $1 AND id = $2 AND deleted_at IS NULL RETURNING user_id, id, subject, students, time AS "time: DbTime", timerange AS "timerange: DbTimerange", timezone, hourly_rate, archived"#, u.as_str(), id, archived)
+		.fetch_optional(db)
+		.await? else {
+		return Ok(None);
+	};
+
+	Ok(Some(model::convert_ts(ts).context("invalid data in db")?))
+}
+
+// Timeslots of all users, which ended before `before` and can be archived automatically.
+pub async fn get_finished_timeslots(
+	db: &PgPool,
+	before: NaiveDate,
+) -> anyhow::Result<Vec<WebTimeSlot>> {
+	let timeslots_db: Vec<TimeSlot> = sqlx::query_as!(TimeSlot, r#"SELECT id, user_id, subject, students, time AS "time: DbTime", timerange AS "timerange: DbTimerange", timezone, hourly_rate, archived FROM timeslots WHERE (timerange).finish < $1 AND archived IS NULL AND deleted_at IS NULL"#, before)
+		.fetch_all(db)
+		.await?;
+
+	Ok(timeslots_db
+		.into_iter()
+		.filter_map(model::convert_ts)
+		.collect())
 }
@@ -0,0 +1,96 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::auth::UserId;
+use crate::db::model::{
+	self, DbTime, DbTimerange, Entry, EntryState, StudentState, TimeSlot, WebEntry, WebTimeSlot,
+};
+
+#[derive(Serialize)]
+pub struct DeletedTimeslot {
+	pub timeslot: WebTimeSlot,
+	pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct DeletedEntry {
+	pub entry: WebEntry,
+	pub deleted_at: DateTime<Utc>,
+}
+
+pub async fn get_deleted_timeslots(
+	db: &PgPool,
+	u: &UserId,
+) -> anyhow::Result<Vec<DeletedTimeslot>> {
+	let rows = sqlx::query!(r#"SELECT id, user_id, subject, students, time AS "time: DbTime", timerange AS "timerange: DbTimerange", timezone, hourly_rate, archived, deleted_at AS "deleted_at!" FROM timeslots WHERE user_id = $1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC"#, u.as_str())
+		.fetch_all(db)
+		.await?;
+
+	rows.into_iter()
+		.map(|r| {
+			let timeslot = model::convert_ts(TimeSlot {
+				user_id: r.user_id,
+				id: r.id,
+				subject: r.subject,
+				students: r.students,
+				time: r.time,
+				timerange: r.timerange,
+				timezone: r.timezone,
+				hourly_rate: r.hourly_rate,
+				archived: r.archived,
+			})
+			.context("invalid data in db")?;
+
+			Ok(DeletedTimeslot {
+				timeslot,
+				deleted_at: r.deleted_at,
+			})
+		})
+		.collect()
+}
+
+// Entries of deleted timeslots are only listed as part of the timeslot.
+pub async fn get_deleted_entries(db: &PgPool, u: &UserId) -> anyhow::Result<Vec<DeletedEntry>> {
+	let rows = sqlx::query!(r#"SELECT e.user_id, e.index, e.timeslot_id, e.state_enum AS "state_enum: EntryState", e.students AS "students: Vec<StudentState>", e.deleted_at AS "deleted_at!" FROM entries e JOIN timeslots t ON t.id = e.timeslot_id WHERE e.user_id = $1 AND e.deleted_at IS NOT NULL AND t.deleted_at IS NULL ORDER BY e.deleted_at DESC"#, u.as_str())
+		.fetch_all(db)
+		.await?;
+
+	rows.into_iter()
+		.map(|r| {
+			let entry = model::convert_entry(Entry {
+				user_id: r.user_id,
+				index: r.index,
+				timeslot_id: r.timeslot_id,
+				state_enum: r.state_enum,
+				students: r.students,
+			})
+			.context("invalid data in db")?;
+
+			Ok(DeletedEntry {
+				entry,
+				deleted_at: r.deleted_at,
+			})
+		})
+		.collect()
+}
+
+// Returns the number of permanently deleted timeslots and entries.
+pub async fn purge_deleted(db: &PgPool, cutoff: DateTime<Utc>) -> anyhow::Result<(u64, u64)> {
+	let mut tx = db.begin().await?;
+
+	let entries = sqlx::query!("DELETE FROM entries WHERE deleted_at < $1", cutoff)
+		.execute(&mut *tx)
+		.await?
+		.rows_affected();
+
+	let timeslots = sqlx::query!("DELETE FROM timeslots WHERE deleted_at < $1", cutoff)
+		.execute(&mut *tx)
+		.await?
+		.rows_affected();
+
+	tx.commit().await?;
+
+	Ok((timeslots, entries))
+}
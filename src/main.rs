@@ -17,6 +17,7 @@ mod api;
 mod auth;
 mod configuration;
 mod db;
+mod tasks;
 mod util;
 
 #[tokio::main]
@@ -53,8 +54,23 @@ async fn main() {
 
 	let cfg: configuration::Config = cfg_builder.try_deserialize().unwrap();
 
+	if cfg.redaction.disabled {
+		util::logging::disable_redaction();
+		tracing::warn!("redaction of student names in logs is disabled");
+	}
+
 	let db = get_pool(&cfg).await;
 
+	if std::env::args().nth(1).as_deref() == Some("encrypt-existing") {
+		let (timeslots, entries) = db::queries::encryption::encrypt_existing(&db)
+			.await
+			.expect("Couldn't encrypt existing rows");
+		tracing::info!(timeslots, entries, "encrypted existing student names");
+		return;
+	}
+
+	tasks::spawn(db.clone(), &cfg);
+
 	let auth = Authenticator::new(
 		cfg.auth.clientid.clone(),
 		cfg.auth.clientsecret.clone(),
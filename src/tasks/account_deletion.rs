@@ -0,0 +1,18 @@
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::db::queries::account::{delete_account, get_due_deletions};
+
+pub async fn delete_due_accounts(db: PgPool) -> anyhow::Result<()> {
+	let due = get_due_deletions(&db).await?;
+
+	for user_id in &due {
+		delete_account(&db, user_id).await?;
+	}
+
+	if !due.is_empty() {
+		info!(count = due.len(), "deleted accounts");
+	}
+
+	Ok(())
+}
@@ -0,0 +1,50 @@
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::api::logic::entry::missing_entries;
+use crate::auth::UserId;
+use crate::db::queries::audit::{log_timeslot, AuditAction, AuditContext};
+use crate::db::queries::timeslot::{get_finished_timeslots, set_timeslot_archived};
+
+// Archives timeslots, which have ended and don't miss any entries.
+pub async fn archive_finished(db: PgPool) -> anyhow::Result<()> {
+	let today = Utc::now().date_naive();
+
+	let mut archived = 0;
+
+	for ts in get_finished_timeslots(&db, today).await? {
+		let u = UserId::from_db(ts.user_id.clone());
+
+		if !missing_entries(&db, &u, &ts).await?.is_empty() {
+			continue;
+		}
+
+		let audit = AuditContext {
+			user_id: ts.user_id.clone(),
+			request_id: None,
+		};
+
+		let mut tx = db.begin().await?;
+
+		if let Some(after) = set_timeslot_archived(&mut *tx, &u, ts.id, true).await? {
+			log_timeslot(
+				&mut *tx,
+				&audit,
+				AuditAction::Update,
+				Some(&ts),
+				Some(&after),
+			)
+			.await?;
+			archived += 1;
+		}
+
+		tx.commit().await?;
+	}
+
+	if archived > 0 {
+		info!(archived, "archived finished timeslots");
+	}
+
+	Ok(())
+}
@@ -0,0 +1,51 @@
+use std::future::Future;
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::configuration::Config;
+
+mod account_deletion;
+mod archive;
+mod retention;
+mod trash;
+
+async fn run_periodically<F, Fut>(period: Duration, mut task: F)
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = anyhow::Result<()>>,
+{
+	let mut interval = tokio::time::interval(period);
+
+	loop {
+		interval.tick().await;
+
+		if let Err(e) = task().await {
+			error!(?e, "error while running background task");
+		}
+	}
+}
+
+pub fn spawn(db: PgPool, cfg: &Config) {
+	let deletion_db = db.clone();
+	tokio::spawn(run_periodically(Duration::from_hours(1), move || {
+		account_deletion::delete_due_accounts(deletion_db.clone())
+	}));
+
+	let archive_db = db.clone();
+	tokio::spawn(run_periodically(Duration::from_hours(24), move || {
+		archive::archive_finished(archive_db.clone())
+	}));
+
+	let trash_db = db.clone();
+	let trash_cfg = cfg.trash;
+	tokio::spawn(run_periodically(Duration::from_hours(1), move || {
+		trash::purge_deleted(trash_db.clone(), trash_cfg)
+	}));
+
+	let retention_cfg = cfg.retention;
+	tokio::spawn(run_periodically(Duration::from_hours(24), move || {
+		retention::purge_expired(db.clone(), retention_cfg)
+	}));
+}
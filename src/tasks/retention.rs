@@ -0,0 +1,41 @@
+use chrono::{Months, Utc};
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::configuration::{Retention, RetentionMode};
+use crate::db::queries::retention::{anonymise_timeslot, delete_expired, get_expired_timeslots};
+
+pub async fn purge_expired(db: PgPool, retention: Retention) -> anyhow::Result<()> {
+	let Some(months) = retention.months else {
+		return Ok(());
+	};
+
+	let cutoff = Utc::now()
+		.date_naive()
+		.checked_sub_months(Months::new(months))
+		.ok_or_else(|| anyhow::anyhow!("retention period too long"))?;
+
+	match retention.mode {
+		RetentionMode::Delete => {
+			let (timeslots, entries) = delete_expired(&db, cutoff).await?;
+
+			if timeslots > 0 {
+				info!(%cutoff, timeslots, entries, "deleted expired timeslots and entries");
+			}
+		}
+		RetentionMode::Anonymise => {
+			let expired = get_expired_timeslots(&db, cutoff).await?;
+
+			let mut entries = 0;
+			for id in &expired {
+				entries += anonymise_timeslot(&db, *id).await?;
+			}
+
+			if !expired.is_empty() {
+				info!(%cutoff, timeslots = expired.len(), entries, "anonymised expired timeslots and entries");
+			}
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,18 @@
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::configuration::Trash;
+use crate::db::queries::trash;
+
+pub async fn purge_deleted(db: PgPool, cfg: Trash) -> anyhow::Result<()> {
+	let cutoff = Utc::now() - Duration::days(cfg.days.into());
+
+	let (timeslots, entries) = trash::purge_deleted(&db, cutoff).await?;
+
+	if timeslots > 0 || entries > 0 {
+		info!(%cutoff, timeslots, entries, "purged deleted timeslots and entries");
+	}
+
+	Ok(())
+}
@@ -1,3 +1,9 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_subscriber::fmt::format::{json, FmtSpan, JsonFields};
 use tracing_subscriber::layer::SubscriberExt;
@@ -38,3 +44,68 @@ pub fn basic_logging() {
 		.with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
 		.init();
 }
+
+static REDACTION_DISABLED: AtomicBool = AtomicBool::new(false);
+
+// Only meant for development.
+pub fn disable_redaction() {
+	REDACTION_DISABLED.store(true, Ordering::Relaxed);
+}
+
+fn salt() -> &'static [u8; 16] {
+	static SALT: OnceLock<[u8; 16]> = OnceLock::new();
+
+	SALT.get_or_init(|| {
+		let mut salt = [0u8; 16];
+		rand::thread_rng().fill_bytes(&mut salt);
+		salt
+	})
+}
+
+// Pseudonymises student names when formatted, so they never end up in logs.
+// The salt is random per process, so pseudonyms are only stable until a restart.
+pub struct Redacted<'a>(pub &'a str);
+
+impl fmt::Display for Redacted<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if REDACTION_DISABLED.load(Ordering::Relaxed) {
+			return f.write_str(self.0);
+		}
+
+		let hash = Sha256::new()
+			.chain_update(salt())
+			.chain_update(self.0.as_bytes())
+			.finalize();
+
+		f.write_str("student#")?;
+		for b in &hash[..4] {
+			write!(f, "{b:02x}")?;
+		}
+
+		Ok(())
+	}
+}
+
+impl fmt::Debug for Redacted<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "\"{self}\"")
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Redacted;
+
+	#[test]
+	fn test_redacted() {
+		let redacted = Redacted("Erika Mustermann").to_string();
+
+		assert!(!redacted.contains("Erika"));
+		assert_eq!(redacted, Redacted("Erika Mustermann").to_string());
+		assert_ne!(redacted, Redacted("Max Mustermann").to_string());
+		assert_eq!(
+			format!("{:?}", Redacted("Erika Mustermann")),
+			format!("\"{redacted}\"")
+		);
+	}
+}
@@ -1,4 +1,10 @@
-use chrono::{Datelike, IsoWeek, NaiveDate};
+use std::ops::Range;
+
+use chrono::{
+	DateTime, Datelike, Duration, IsoWeek, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone,
+	Weekday,
+};
+use chrono_tz::Tz;
 
 pub mod logging;
 
@@ -21,7 +27,110 @@ pub fn round_up_to_multiple(n: i64, multiple: i64) -> i64 {
 }
 
 pub fn create_isoweek(year: i32, week: u32) -> Option<IsoWeek> {
-	let date = NaiveDate::from_isoywd_opt(year, week, chrono::Weekday::Mon)?;
+	let date = NaiveDate::from_isoywd_opt(year, week, Weekday::Mon)?;
 
 	Some(date.iso_week())
 }
+
+// Returns the dates from the monday of `range.start` until the sunday of `range.end` (inclusive).
+pub fn isoweek_date_range(range: Range<IsoWeek>) -> Option<Range<NaiveDate>> {
+	let start = NaiveDate::from_isoywd_opt(range.start.year(), range.start.week(), Weekday::Mon)?;
+	let end = NaiveDate::from_isoywd_opt(range.end.year(), range.end.week(), Weekday::Sun)?;
+
+	Some(start..end)
+}
+
+// Local times are resolved deterministically around DST transitions:
+// Ambiguous times use the earlier instant, times in a gap are shifted forward by the length of the gap.
+pub fn resolve_local_time(time: NaiveDateTime, tz: Tz) -> Option<DateTime<Tz>> {
+	match tz.from_local_datetime(&time) {
+		LocalResult::Single(t) => Some(t),
+		LocalResult::Ambiguous(earliest, _) => Some(earliest),
+		LocalResult::None => {
+			// Interpreting the time with the offset from before the gap, moves it past the gap.
+			let before = tz
+				.offset_from_utc_datetime(&time.checked_sub_signed(Duration::days(1))?)
+				.fix();
+
+			let utc =
+				time.checked_sub_signed(Duration::seconds(before.local_minus_utc().into()))?;
+
+			Some(tz.from_utc_datetime(&utc))
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+	use chrono_tz::Tz;
+
+	use super::resolve_local_time;
+
+	fn time(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+		NaiveDate::from_ymd_opt(y, m, d)
+			.unwrap()
+			.and_time(NaiveTime::from_hms_opt(h, min, 0).unwrap())
+	}
+
+	fn resolve(t: NaiveDateTime, tz: Tz) -> String {
+		resolve_local_time(t, tz).unwrap().to_rfc3339()
+	}
+
+	#[test]
+	fn test_resolve_local_time_gap() {
+		use chrono_tz::{America, Australia, Europe};
+
+		assert_eq!(
+			resolve(time(2024, 3, 31, 2, 30), Europe::Berlin),
+			"2024-03-31T03:30:00+02:00"
+		);
+		assert_eq!(
+			resolve(time(2024, 3, 10, 2, 0), America::New_York),
+			"2024-03-10T03:00:00-04:00"
+		);
+		assert_eq!(
+			resolve(time(2024, 10, 6, 2, 59), Australia::Sydney),
+			"2024-10-06T03:59:00+11:00"
+		);
+		// Only shifts by 30 minutes
+		assert_eq!(
+			resolve(time(2024, 10, 6, 2, 15), Australia::Lord_Howe),
+			"2024-10-06T02:45:00+11:00"
+		);
+	}
+
+	#[test]
+	fn test_resolve_local_time_ambiguous() {
+		use chrono_tz::{America, Australia, Europe};
+
+		assert_eq!(
+			resolve(time(2024, 10, 27, 2, 30), Europe::Berlin),
+			"2024-10-27T02:30:00+02:00"
+		);
+		assert_eq!(
+			resolve(time(2024, 11, 3, 1, 30), America::New_York),
+			"2024-11-03T01:30:00-04:00"
+		);
+		assert_eq!(
+			resolve(time(2024, 4, 7, 2, 30), Australia::Sydney),
+			"2024-04-07T02:30:00+11:00"
+		);
+		assert_eq!(
+			resolve(time(2024, 4, 7, 1, 45), Australia::Lord_Howe),
+			"2024-04-07T01:45:00+11:00"
+		);
+	}
+
+	#[test]
+	fn test_resolve_local_time_unaffected() {
+		assert_eq!(
+			resolve(time(2024, 3, 31, 1, 59), chrono_tz::Europe::Berlin),
+			"2024-03-31T01:59:00+01:00"
+		);
+		assert_eq!(
+			resolve(time(2024, 3, 31, 12, 0), chrono_tz::UTC),
+			"2024-03-31T12:00:00+00:00"
+		);
+	}
+}